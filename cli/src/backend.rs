@@ -0,0 +1,88 @@
+//  This Source Code Form is subject to the terms of the Mozilla Public
+//  License, v. 2.0. If a copy of the MPL was not distributed with this
+//  file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use crate::{
+    cardano::{Proposal, ProtocolParameters, Tip},
+    error::Error,
+    pallas_extra::Slots,
+};
+use pallas_addresses::{Network, ShelleyAddress, StakeAddress};
+use pallas_crypto::hash::Hash;
+use pallas_primitives::conway::{
+    AssetName, GovActionId, PlutusData, PolicyId, PostAlonzoTransactionOutput, TransactionInput,
+    Tx, Vote,
+};
+use uplc::tx::ResolvedInput;
+
+/// Everything commands need to know about the chain, regardless of where it comes from. Blockfrost
+/// (through 'Cardano') is the only data source for now.
+// NOTE: Futures needn't be 'Send': commands run on a single thread.
+#[allow(async_fn_in_trait)]
+pub trait Backend {
+    fn network_id(&self) -> Network;
+
+    fn slots(&self) -> Slots;
+
+    async fn protocol_parameters(&self) -> Result<ProtocolParameters, Error>;
+
+    /// Every transaction that minted or burned the given asset.
+    async fn minting(&self, policy_id: &PolicyId, asset_name: &AssetName)
+        -> Result<Vec<Tx>, Error>;
+
+    /// The transaction, provided it was ever submitted on-chain.
+    async fn transaction_by_hash(&self, tx_hash: &str) -> Result<Option<Tx>, Error>;
+
+    /// The datum behind a datum hash, provided it was ever witnessed on-chain.
+    async fn datum(&self, datum_hash: &Hash<32>) -> Result<Option<PlutusData>, Error>;
+
+    /// The DRep's latest recorded vote on the given proposal, if any.
+    async fn drep_vote(
+        &self,
+        script_hash: &Hash<28>,
+        proposal: &GovActionId,
+    ) -> Result<Option<Vote>, Error>;
+
+    /// Whether the stake address is currently registered. One never seen on-chain isn't.
+    async fn is_registered(&self, stake_address: &StakeAddress) -> Result<bool, Error>;
+
+    async fn tip(&self) -> Result<Tip, Error>;
+
+    /// The first slot *after* the given epoch, extrapolated from the current epoch.
+    async fn epoch_boundary(&self, epoch: u64) -> Result<u64, Error>;
+
+    /// The proposal, provided it was ever submitted on-chain.
+    async fn proposal(&self, id: &GovActionId) -> Result<Option<Proposal>, Error>;
+
+    /// Resolve each input in turn; all of them must be found.
+    async fn resolve_many(
+        &self,
+        inputs: &[&TransactionInput],
+    ) -> Result<Vec<ResolvedInput>, Error> {
+        let mut resolved = vec![];
+        for i in inputs {
+            resolved.push(self.resolve(i).await.ok_or_else(|| Error::InputNotFound {
+                input: format!("{}#{}", i.transaction_id, i.index),
+            })?);
+        }
+        Ok(resolved)
+    }
+
+    /// Whether an output still sits, unspent, at its address. Outputs that were never produced
+    /// are an error, not spent.
+    async fn is_unspent(&self, input: &TransactionInput) -> Result<bool, Error>;
+
+    /// Every output currently sitting at the given address. An address that was never used has
+    /// none.
+    async fn utxos_at(&self, address: &ShelleyAddress) -> Result<Vec<ResolvedInput>, Error>;
+
+    /// Every output currently holding the given asset, wherever it sits. An asset that was never
+    /// minted, or has since been burned, is held by none.
+    async fn asset_utxos(
+        &self,
+        policy: &Hash<28>,
+        name: &AssetName,
+    ) -> Result<Vec<(TransactionInput, PostAlonzoTransactionOutput)>, Error>;
+
+    async fn resolve(&self, input: &TransactionInput) -> Option<ResolvedInput>;
+}
@@ -0,0 +1,88 @@
+//  This Source Code Form is subject to the terms of the Mozilla Public
+//  License, v. 2.0. If a copy of the MPL was not distributed with this
+//  file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use crate::pallas_extra::BuildParams;
+use pallas_codec::minicbor as cbor;
+use pallas_crypto::hash::{Hash, Hasher};
+use pallas_primitives::conway::ExUnits;
+use std::{collections::BTreeMap, fs, path::Path};
+use uplc::tx::ResolvedInput;
+
+/// Fee and execution units a transaction has converged to, indexed by a fingerprint of everything
+/// that went into building that transaction.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Default)]
+struct ConvergenceCache(BTreeMap<String, Converged>);
+
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+struct Converged {
+    fee: u64,
+    ex_units: Vec<(u64, u64)>,
+}
+
+// The first draft of a transaction (i.e. without fee nor execution units) captures all of its
+// inputs, outputs, certificates, scripts, etc. Together with the resolved inputs, the fee
+// parameters and the expected number of signatures, this is everything the fixed point depends on.
+pub fn fingerprint(
+    params: &BuildParams,
+    resolved_inputs: &[ResolvedInput],
+    expected_signers: Option<usize>,
+    draft: &[u8],
+) -> Hash<32> {
+    let mut preimage = draft.to_vec();
+
+    for resolved_input in resolved_inputs {
+        cbor::encode(&resolved_input.input, &mut preimage).unwrap();
+        cbor::encode(&resolved_input.output, &mut preimage).unwrap();
+    }
+
+    preimage.extend(params.fee_constant.to_be_bytes());
+    preimage.extend(params.fee_coefficient.to_be_bytes());
+    preimage.extend(params.price_mem.to_bits().to_be_bytes());
+    preimage.extend(params.price_steps.to_bits().to_be_bytes());
+
+    cbor::encode(expected_signers.map(|n| n as u64), &mut preimage).unwrap();
+
+    Hasher::<256>::hash(&preimage)
+}
+
+pub fn lookup(path: &Path, fingerprint: &Hash<32>) -> Option<(u64, Vec<ExUnits>)> {
+    let ConvergenceCache(mut entries) = load(path);
+    entries.remove(&fingerprint.to_string()).map(|converged| {
+        (
+            converged.fee,
+            converged
+                .ex_units
+                .into_iter()
+                .map(|(mem, steps)| ExUnits { mem, steps })
+                .collect(),
+        )
+    })
+}
+
+pub fn store(path: &Path, fingerprint: &Hash<32>, fee: u64, ex_units: &[ExUnits]) {
+    let ConvergenceCache(mut entries) = load(path);
+
+    entries.insert(
+        fingerprint.to_string(),
+        Converged {
+            fee,
+            ex_units: ex_units.iter().map(|e| (e.mem, e.steps)).collect(),
+        },
+    );
+
+    // NOTE: The cache is merely an optimization, failing to persist it shouldn't fail the build.
+    if let Err(e) = fs::write(
+        path,
+        serde_json::to_string_pretty(&ConvergenceCache(entries)).unwrap(),
+    ) {
+        eprintln!("warning: failed to write convergence cache at {path:?}: {e}");
+    }
+}
+
+fn load(path: &Path) -> ConvergenceCache {
+    fs::read(path)
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or_default()
+}
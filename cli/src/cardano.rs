@@ -2,18 +2,28 @@
 //  License, v. 2.0. If a copy of the MPL was not distributed with this
 //  file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
-use crate::pallas_extra::BuildParams;
-use blockfrost::{BlockfrostAPI, Pagination};
+use crate::{
+    backend::Backend,
+    error::Error,
+    pallas_extra::{BuildParams, Slots},
+    timings::Timings,
+    warnings::{Warning, Warnings},
+};
+use blockfrost::{BlockfrostAPI, BlockfrostError, Pagination};
 use blockfrost_openapi::models::{
-    asset_history_inner::Action, tx_content_output_amount_inner::TxContentOutputAmountInner,
+    address_utxo_content_inner::AddressUtxoContentInner,
+    asset_addresses_inner::AssetAddressesInner, asset_history_inner::Action,
+    tx_content_output_amount_inner::TxContentOutputAmountInner,
 };
-use pallas_addresses::Network;
+use pallas_addresses::{Network, ShelleyAddress, StakeAddress};
+use pallas_codec::utils::CborWrap;
 use pallas_codec::{minicbor as cbor, utils::NonEmptyKeyValuePairs};
+use pallas_crypto::hash::Hash;
 use pallas_primitives::conway::{
-    AssetName, PolicyId, PostAlonzoTransactionOutput, TransactionInput, TransactionOutput, Tx,
-    Value,
+    AssetName, DatumOption, GovActionId, PlutusData, PolicyId, PostAlonzoTransactionOutput,
+    TransactionInput, TransactionOutput, Tx, Value, Vote,
 };
-use std::{collections::BTreeMap, env};
+use std::{cell::RefCell, collections::BTreeMap, env, time::Instant};
 use uplc::tx::ResolvedInput;
 
 pub struct Cardano {
@@ -21,7 +31,40 @@ pub struct Cardano {
     client: reqwest::Client,
     network: Network,
     network_prefix: String,
-    project_id: String,
+    project_id: Secret,
+    timings: Timings,
+    warnings: Warnings,
+    epoch: Option<u64>,
+    overrides: ProtocolParametersOverrides,
+    protocol_parameters: Option<ProtocolParameters>,
+    resolved_inputs: Option<Vec<ResolvedInput>>,
+    memo: Memo,
+}
+
+/// Answers to queries already made during this invocation; so that repeated ones (e.g. when
+/// recovering rules for several proposals) only reach the network once. Only successful lookups
+/// are kept.
+#[derive(Default)]
+struct Memo {
+    protocol_parameters: RefCell<Option<ProtocolParameters>>,
+    minting: RefCell<BTreeMap<(PolicyId, AssetName), Vec<Tx>>>,
+    transactions: RefCell<BTreeMap<String, Tx>>,
+    resolved_inputs: RefCell<BTreeMap<TransactionInput, ResolvedInput>>,
+}
+
+/// A credential, which never shows in logs nor error messages; only in the requests needing it.
+pub struct Secret(String);
+
+impl Secret {
+    fn expose(&self) -> &str {
+        self.0.as_str()
+    }
+}
+
+impl std::fmt::Debug for Secret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<redacted>")
+    }
 }
 
 const UNIT_LOVELACE: &str = "lovelace";
@@ -30,9 +73,13 @@ const MAINNET_PREFIX: &str = "mainnet";
 const PREPROD_PREFIX: &str = "preprod";
 const PREVIEW_PREFIX: &str = "preview";
 
+pub const NETWORKS: [&str; 3] = [MAINNET_PREFIX, PREPROD_PREFIX, PREVIEW_PREFIX];
+
 const ENV_PROJECT_ID: &str = "BLOCKFROST_PROJECT_ID";
 
-#[derive(Debug)]
+const SANE_COLLATERAL_PERCENT: std::ops::RangeInclusive<u64> = 100..=1000;
+
+#[derive(Debug, Clone)]
 pub struct ProtocolParameters {
     pub collateral_percent: f64,
     pub cost_model_v3: Vec<i64>,
@@ -42,6 +89,111 @@ pub struct ProtocolParameters {
     pub min_utxo_deposit_coefficient: u64,
     pub price_mem: f64,
     pub price_steps: f64,
+    pub stake_deposit: u64,
+}
+
+/// Values taking precedence over those fetched from the network, for experimentation on
+/// devnets or to assess the sensitivity of a transaction to parameter changes.
+#[derive(Debug, Default, Clone)]
+pub struct ProtocolParametersOverrides {
+    pub collateral_percent: Option<u64>,
+    pub fee_constant: Option<u64>,
+    pub fee_coefficient: Option<u64>,
+    pub price_mem: Option<f64>,
+    pub price_steps: Option<f64>,
+    /// A percentage by which to raise the fee coefficient and the price of steps, on top of any
+    /// of the above.
+    pub fee_bump: u64,
+}
+
+impl ProtocolParameters {
+    // Serialise parameters following the format of cardano-cli's 'query protocol-parameters'.
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "collateralPercentage": (self.collateral_percent * 1e2).round() as u64,
+            "costModels": {
+                "PlutusV3": self.cost_model_v3,
+            },
+            "dRepDeposit": self.drep_deposit,
+            "executionUnitPrices": {
+                "priceMemory": self.price_mem,
+                "priceSteps": self.price_steps,
+            },
+            "stakeAddressDeposit": self.stake_deposit,
+            "txFeeFixed": self.fee_constant,
+            "txFeePerByte": self.fee_coefficient,
+            "utxoCostPerByte": self.min_utxo_deposit_coefficient,
+        })
+    }
+
+    // Parse parameters following the format of cardano-cli's 'query protocol-parameters'; fields
+    // irrelevant to building are ignored. Cost models may also be keyed by network name (e.g.
+    // 'costModels.preprod.PlutusV3'), so that one file serves several networks; the given network
+    // then picks one.
+    pub fn from_json(json: &serde_json::Value, network: Option<&str>) -> Result<Self, String> {
+        let field = |path: &str| {
+            path.split('.')
+                .try_fold(json, |json, key| json.get(key))
+                .ok_or_else(|| format!("missing '{path}' in protocol parameters"))
+        };
+
+        let uint = |path: &str| {
+            field(path)?
+                .as_u64()
+                .ok_or_else(|| format!("expected '{path}' to be a positive integer"))
+        };
+
+        let float = |path: &str| {
+            field(path)?
+                .as_f64()
+                .ok_or_else(|| format!("expected '{path}' to be a number"))
+        };
+
+        let cost_model_path = if field("costModels")?.get("PlutusV3").is_some() {
+            "costModels.PlutusV3".to_string()
+        } else {
+            let network = network.ok_or_else(|| {
+                "cost models are keyed by network, but there's no telling which one".to_string()
+            })?;
+            format!("costModels.{network}.PlutusV3")
+        };
+
+        let cost_model_v3 = field(&cost_model_path)?
+            .as_array()
+            .and_then(|costs| costs.iter().map(|cost| cost.as_i64()).collect())
+            .ok_or_else(|| format!("expected '{cost_model_path}' to be an array of integers"))?;
+
+        Ok(ProtocolParameters {
+            collateral_percent: uint("collateralPercentage")? as f64 / 1e2,
+            cost_model_v3,
+            drep_deposit: uint("dRepDeposit")?,
+            fee_constant: uint("txFeeFixed")?,
+            fee_coefficient: uint("txFeePerByte")?,
+            min_utxo_deposit_coefficient: uint("utxoCostPerByte")?,
+            price_mem: float("executionUnitPrices.priceMemory")?,
+            price_steps: float("executionUnitPrices.priceSteps")?,
+            stake_deposit: uint("stakeAddressDeposit")?,
+        })
+    }
+}
+
+impl ProtocolParametersOverrides {
+    fn apply(&self, params: ProtocolParameters) -> ProtocolParameters {
+        ProtocolParameters {
+            collateral_percent: self
+                .collateral_percent
+                .map(|percent| percent as f64 / 1e2)
+                .unwrap_or(params.collateral_percent),
+            fee_constant: self.fee_constant.unwrap_or(params.fee_constant),
+            fee_coefficient: (self.fee_coefficient.unwrap_or(params.fee_coefficient)
+                * (100 + self.fee_bump))
+                .div_ceil(100),
+            price_mem: self.price_mem.unwrap_or(params.price_mem),
+            price_steps: self.price_steps.unwrap_or(params.price_steps)
+                * (1.0 + self.fee_bump as f64 / 1e2),
+            ..params
+        }
+    }
 }
 
 impl From<&ProtocolParameters> for BuildParams {
@@ -51,77 +203,211 @@ impl From<&ProtocolParameters> for BuildParams {
             fee_coefficient: params.fee_coefficient,
             price_mem: params.price_mem,
             price_steps: params.price_steps,
+            min_utxo_deposit_coefficient: params.min_utxo_deposit_coefficient,
         }
     }
 }
 
 impl Cardano {
-    pub fn new() -> Self {
-        let project_id =
-            env::var(ENV_PROJECT_ID).unwrap_or_else(|_| panic!("Missing {ENV_PROJECT_ID} env var"));
-        let api = BlockfrostAPI::new(project_id.as_str(), Default::default());
+    // NOTE: Credentials only ever come from the environment, and are checked upfront; so that a
+    // missing or mistyped one fails before anything else, without echoing its value back.
+    pub fn new() -> Result<Self, Error> {
+        let project_id = match env::var(ENV_PROJECT_ID) {
+            Ok(project_id) if !project_id.is_empty() => Secret(project_id),
+            _ => return Err(Error::MissingCredentials(ENV_PROJECT_ID)),
+        };
+
+        let network_prefix = NETWORKS
+            .into_iter()
+            .find(|prefix| project_id.expose().starts_with(prefix))
+            .ok_or(Error::UnrecognizedCredentials(ENV_PROJECT_ID))?;
+
+        Ok(Self::with_credentials(network_prefix, project_id))
+    }
+
+    /// Like 'new', but on an explicit network; which must then agree with the credentials.
+    pub fn new_on(network: &str) -> Result<Self, Error> {
+        let cardano = Self::new()?;
+        if cardano.network_prefix != network {
+            return Err(Error::NetworkMismatch {
+                expected: network.to_string(),
+                actual: cardano.network_prefix,
+            });
+        }
+        Ok(cardano)
+    }
+
+    /// Without any credentials; for when protocol parameters and resolved inputs are all given
+    /// ahead of time. Whatever else needs the network then fails as an offline lookup.
+    pub fn offline(network: &str) -> Self {
+        Self::with_credentials(network, Secret(String::new()))
+    }
+
+    fn with_credentials(network_prefix: &str, project_id: Secret) -> Self {
+        // NOTE: The project id is also how the client picks its network; so, without any, the
+        // network prefix stands in for it.
+        let api = BlockfrostAPI::new(
+            if project_id.expose().is_empty() {
+                network_prefix
+            } else {
+                project_id.expose()
+            },
+            Default::default(),
+        );
         Cardano {
             api,
-            client: reqwest::Client::new(),
-            network: if project_id.starts_with(MAINNET_PREFIX) {
+            client: http_client(false),
+            network: if network_prefix == MAINNET_PREFIX {
                 Network::Mainnet
             } else {
                 Network::Testnet
             },
-            network_prefix: if project_id.starts_with(MAINNET_PREFIX) {
-                MAINNET_PREFIX.to_string()
-            } else if project_id.starts_with(PREPROD_PREFIX) {
-                PREPROD_PREFIX.to_string()
-            } else if project_id.starts_with(PREVIEW_PREFIX) {
-                PREVIEW_PREFIX.to_string()
-            } else {
-                panic!("unexpected project id prefix")
-            },
+            network_prefix: network_prefix.to_string(),
             project_id,
+            timings: Timings::default(),
+            warnings: Warnings::default(),
+            epoch: None,
+            overrides: ProtocolParametersOverrides::default(),
+            protocol_parameters: None,
+            resolved_inputs: None,
+            memo: Memo::default(),
         }
     }
 
-    pub fn network_id(&self) -> Network {
-        self.network
+    pub fn with_timings(self, timings: Timings) -> Self {
+        Cardano { timings, ..self }
     }
 
-    pub async fn protocol_parameters(&self) -> ProtocolParameters {
-        let params = self
-            .api
-            .epochs_latest_parameters()
+    pub fn with_warnings(self, warnings: Warnings) -> Self {
+        Cardano { warnings, ..self }
+    }
+
+    /// Build against the parameters of a past epoch rather than the current ones, cost model
+    /// included; e.g. to reproduce a historical transaction.
+    pub fn with_epoch(self, epoch: Option<u64>) -> Self {
+        Cardano { epoch, ..self }
+    }
+
+    pub fn with_overrides(self, overrides: ProtocolParametersOverrides) -> Self {
+        Cardano { overrides, ..self }
+    }
+
+    pub fn with_fee_bump(mut self, fee_bump: u64) -> Self {
+        self.overrides.fee_bump = fee_bump;
+        self
+    }
+
+    /// Parameters obtained ahead of time (e.g. from the cardano-cli), used instead of fetching any
+    /// from the network. Overrides still apply on top.
+    pub fn with_protocol_parameters(self, protocol_parameters: Option<ProtocolParameters>) -> Self {
+        Cardano {
+            protocol_parameters,
+            ..self
+        }
+    }
+
+    /// Inputs resolved ahead of time (e.g. captured by a previous, online, run). When given, inputs
+    /// are only ever resolved from them, never from the network, and are trusted to be unspent.
+    pub fn with_resolved_inputs(self, resolved_inputs: Option<Vec<ResolvedInput>>) -> Self {
+        Cardano {
+            resolved_inputs,
+            ..self
+        }
+    }
+
+    pub fn network_name(&self) -> &str {
+        &self.network_prefix
+    }
+
+    // Without credentials, whatever wasn't given ahead of time can't be looked up at all; which
+    // is worth telling apart from the provider failing.
+    fn online(&self, lookup: &'static str) -> Result<(), Error> {
+        if self.project_id.expose().is_empty() {
+            return Err(Error::OfflineLookup(lookup));
+        }
+        Ok(())
+    }
+
+    /// Total live stake (in lovelace) delegated to the script-based DRep. A registered DRep without
+    /// any delegation has a stake of zero, which is distinct from an unregistered one.
+    pub async fn drep_stake(&self, script_hash: &Hash<28>) -> Result<u64, Error> {
+        self.online("drep lookup")?;
+
+        // NOTE: Governance endpoints aren't part of the Rust SDK (yet).
+        let started = Instant::now();
+        let drep_id = drep_id(script_hash);
+        let response = self
+            .client
+            .get(format!(
+                "https://cardano-{}.blockfrost.io/api/v0/governance/dreps/{}",
+                self.network_prefix, drep_id
+            ))
+            .header("Accept", "application/json")
+            .header("project_id", self.project_id.expose())
+            .send()
             .await
-            .expect("failed to fetch protocol parameters");
+            .map_err(|e| Error::ProviderUnavailable(e.to_string()))?;
 
-        ProtocolParameters {
-            collateral_percent: (params
+        let stake = match response.status() {
+            reqwest::StatusCode::OK => {
+                let DRepById { amount } = response
+                    .json::<DRepById>()
+                    .await
+                    .map_err(|e| Error::ProviderUnavailable(e.to_string()))?;
+                amount.parse().map_err(|e| {
+                    Error::ProviderUnavailable(format!("unexpected DRep stake '{amount}': {e}"))
+                })
+            }
+            reqwest::StatusCode::NOT_FOUND => Err(Error::DRepNotFound { drep_id }),
+            status => Err(Error::ProviderUnavailable(format!(
+                "unexpected response from Blockfrost: {status}"
+            ))),
+        };
+        self.timings.record("drep lookup", started);
+        stake
+    }
+
+    async fn fetch_protocol_parameters(&self) -> Result<ProtocolParameters, Error> {
+        if let Some(params) = self.memo.protocol_parameters.borrow().as_ref() {
+            return Ok(params.clone());
+        }
+
+        self.online("protocol parameters")?;
+
+        let started = Instant::now();
+        let params = match self.epoch {
+            None => self
+                .api
+                .epochs_latest_parameters()
+                .await
+                .map_err(|e| Error::ProviderUnavailable(e.to_string()))?,
+            Some(epoch) => self
+                .api
+                .epochs_parameters(epoch as i32)
+                .await
+                .map_err(|_| Error::EpochParametersUnavailable { epoch })?,
+        };
+        self.timings.record("protocol parameters", started);
+
+        // NOTE: Past epochs may have had a different cost model; mixing the bundled one with their
+        // fees would yield a script integrity hash that matches neither.
+        let cost_model_v3 = match self.epoch {
+            None => bundled_cost_model_v3(),
+            Some(epoch) => params
+                .cost_models
+                .as_ref()
+                .and_then(|cost_models| cost_models.get("PlutusV3"))
+                .and_then(cost_model_from_json)
+                .ok_or(Error::EpochParametersUnavailable { epoch })?,
+        };
+
+        let params = ProtocolParameters {
+            collateral_percent: params
                 .collateral_percent
                 .expect("protocol parameters are missing collateral percent")
-                as f64)
+                as f64
                 / 1e2,
-            // NOTE: Blockfrost returns cost models out of order. They must be ordered by their
-            // "ParamName" according to how Plutus defines it, but they are usually found ordered
-            // by ascending keys, unfortunately. Given that they are unlikely to change anytime
-            // soon, I am going to bundle them as-is.
-            cost_model_v3: vec![
-                100788, 420, 1, 1, 1000, 173, 0, 1, 1000, 59957, 4, 1, 11183, 32, 201305, 8356, 4,
-                16000, 100, 16000, 100, 16000, 100, 16000, 100, 16000, 100, 16000, 100, 100, 100,
-                16000, 100, 94375, 32, 132994, 32, 61462, 4, 72010, 178, 0, 1, 22151, 32, 91189,
-                769, 4, 2, 85848, 123203, 7305, -900, 1716, 549, 57, 85848, 0, 1, 1, 1000, 42921,
-                4, 2, 24548, 29498, 38, 1, 898148, 27279, 1, 51775, 558, 1, 39184, 1000, 60594, 1,
-                141895, 32, 83150, 32, 15299, 32, 76049, 1, 13169, 4, 22100, 10, 28999, 74, 1,
-                28999, 74, 1, 43285, 552, 1, 44749, 541, 1, 33852, 32, 68246, 32, 72362, 32, 7243,
-                32, 7391, 32, 11546, 32, 85848, 123203, 7305, -900, 1716, 549, 57, 85848, 0, 1,
-                90434, 519, 0, 1, 74433, 32, 85848, 123203, 7305, -900, 1716, 549, 57, 85848, 0, 1,
-                1, 85848, 123203, 7305, -900, 1716, 549, 57, 85848, 0, 1, 955506, 213312, 0, 2,
-                270652, 22588, 4, 1457325, 64566, 4, 20467, 1, 4, 0, 141992, 32, 100788, 420, 1, 1,
-                81663, 32, 59498, 32, 20142, 32, 24588, 32, 20744, 32, 25933, 32, 24623, 32,
-                43053543, 10, 53384111, 14333, 10, 43574283, 26308, 10, 16000, 100, 16000, 100,
-                962335, 18, 2780678, 6, 442008, 1, 52538055, 3756, 18, 267929, 18, 76433006, 8868,
-                18, 52948122, 18, 1995836, 36, 3227919, 12, 901022, 1, 166917843, 4307, 36, 284546,
-                36, 158221314, 26549, 36, 74698472, 36, 333849714, 1, 254006273, 72, 2174038, 72,
-                2261318, 64571, 4, 207616, 8310, 4, 1293828, 28716, 63, 0, 1, 1006041, 43623, 251,
-                0, 1,
-            ],
+            cost_model_v3,
             drep_deposit: 500_000_000, // NOTE: Missing from Blockfrost
             fee_constant: params.min_fee_b as u64,
             fee_coefficient: params.min_fee_a as u64,
@@ -137,19 +423,84 @@ impl Cardano {
                 .price_step
                 .expect("protocol parameters are missing price step")
                 as f64,
+            stake_deposit: params.key_deposit.parse().unwrap(),
+        };
+
+        *self.memo.protocol_parameters.borrow_mut() = Some(params.clone());
+        Ok(params)
+    }
+}
+
+impl Backend for Cardano {
+    fn network_id(&self) -> Network {
+        self.network
+    }
+
+    // NOTE: Slots are counted from the Shelley hard fork, as Byron ones lasted 20 seconds.
+    fn slots(&self) -> Slots {
+        match self.network_prefix.as_str() {
+            PREPROD_PREFIX => Slots {
+                zero_time: 1655769600000,
+                zero_slot: 86400,
+                slot_length: 1000,
+            },
+            PREVIEW_PREFIX => Slots {
+                zero_time: 1666656000000,
+                zero_slot: 0,
+                slot_length: 1000,
+            },
+            _ => Slots::default(),
         }
     }
 
-    pub async fn minting(&self, policy_id: &PolicyId, asset_name: &AssetName) -> Vec<Tx> {
-        let history = self
+    async fn protocol_parameters(&self) -> Result<ProtocolParameters, Error> {
+        let params = match &self.protocol_parameters {
+            Some(params) => params.clone(),
+            None => self.fetch_protocol_parameters().await?,
+        };
+
+        // NOTE: The collateral percentage is, as its name suggests, a percentage (150 on every
+        // public network). Some devnets report it as a ratio instead (e.g. 1.5), which would make
+        // for a collateral 100 times too small.
+        if self.overrides.collateral_percent.is_none() {
+            let percent = (params.collateral_percent * 1e2).round() as u64;
+            if !SANE_COLLATERAL_PERCENT.contains(&percent) {
+                self.warnings
+                    .push(Warning::SuspiciousCollateralPercent { percent });
+            }
+        }
+
+        Ok(self.overrides.apply(params))
+    }
+
+    async fn minting(
+        &self,
+        policy_id: &PolicyId,
+        asset_name: &AssetName,
+    ) -> Result<Vec<Tx>, Error> {
+        let key = (*policy_id, asset_name.clone());
+        if let Some(txs) = self.memo.minting.borrow().get(&key) {
+            return Ok(txs.clone());
+        }
+
+        self.online("minting lookup")?;
+
+        let started = Instant::now();
+        // NOTE: An asset that was never minted has no history, and is reported as not found.
+        let history = match self
             .api
             .assets_history(
                 &format!("{}{}", hex::encode(policy_id), hex::encode(&asset_name[..])),
                 Pagination::all(),
             )
             .await
-            .ok()
-            .unwrap_or(vec![])
+        {
+            Ok(history) => history,
+            Err(BlockfrostError::Response { reason, .. }) if reason.status_code == 404 => vec![],
+            Err(e) => return Err(Error::ProviderUnavailable(e.to_string())),
+        };
+
+        let history = history
             .into_iter()
             .filter_map(|inner| {
                 if matches!(inner.action, Action::Minted) {
@@ -162,55 +513,400 @@ impl Cardano {
 
         let mut txs: Vec<Tx> = vec![];
         for tx_hash in history {
-            if let Some(tx) = self.transaction_by_hash(&tx_hash).await {
+            if let Some(tx) = self.transaction_by_hash(&tx_hash).await? {
                 txs.push(tx)
             }
         }
-        txs
+        self.timings.record("minting lookup", started);
+        self.memo.minting.borrow_mut().insert(key, txs.clone());
+        Ok(txs)
     }
 
-    pub async fn transaction_by_hash(&self, tx_hash: &str) -> Option<Tx> {
+    async fn transaction_by_hash(&self, tx_hash: &str) -> Result<Option<Tx>, Error> {
+        if let Some(tx) = self.memo.transactions.borrow().get(tx_hash) {
+            return Ok(Some(tx.clone()));
+        }
+
+        self.online("transaction lookup")?;
+
         // NOTE: Not part of the Rust SDK somehow...
+        let started = Instant::now();
         let response = self
             .client
-            .get(&format!(
+            .get(format!(
                 "https://cardano-{}.blockfrost.io/api/v0/txs/{}/cbor",
                 self.network_prefix, tx_hash
             ))
             .header("Accept", "application/json")
-            .header("project_id", self.project_id.as_str())
+            .header("project_id", self.project_id.expose())
             .send()
             .await
-            .unwrap();
+            .map_err(|e| Error::ProviderUnavailable(e.to_string()))?;
 
-        match response.status() {
+        let tx = match response.status() {
             reqwest::StatusCode::OK => {
-                let TxByHash { cbor } = response.json::<TxByHash>().await.unwrap();
-                let tx = cbor::decode(&hex::decode(cbor).unwrap()).unwrap();
+                let TxByHash { cbor } = response
+                    .json::<TxByHash>()
+                    .await
+                    .map_err(|e| Error::ProviderUnavailable(e.to_string()))?;
+                let tx: Tx = hex::decode(&cbor)
+                    .map_err(|e| e.to_string())
+                    .and_then(|bytes| cbor::decode(&bytes).map_err(|e| e.to_string()))
+                    .map_err(|e| {
+                        Error::ProviderUnavailable(format!("malformed transaction {tx_hash}: {e}"))
+                    })?;
                 Some(tx)
             }
-            _ => None,
+            reqwest::StatusCode::NOT_FOUND => None,
+            status => {
+                return Err(Error::ProviderUnavailable(format!(
+                    "unexpected response from Blockfrost: {status}"
+                )))
+            }
+        };
+        self.timings
+            .record(format!("transaction lookup ({tx_hash:.8})"), started);
+        if let Some(ref tx) = tx {
+            self.memo
+                .transactions
+                .borrow_mut()
+                .insert(tx_hash.to_string(), tx.clone());
         }
+        Ok(tx)
     }
 
-    pub async fn resolve_many(&self, inputs: &[&TransactionInput]) -> Vec<ResolvedInput> {
+    async fn datum(&self, datum_hash: &Hash<32>) -> Result<Option<PlutusData>, Error> {
+        self.online("datum lookup")?;
+
+        // NOTE: The SDK's own endpoint for this points at the wrong URL.
+        let started = Instant::now();
+        let response = self
+            .client
+            .get(format!(
+                "https://cardano-{}.blockfrost.io/api/v0/scripts/datum/{}/cbor",
+                self.network_prefix, datum_hash
+            ))
+            .header("Accept", "application/json")
+            .header("project_id", self.project_id.expose())
+            .send()
+            .await
+            .map_err(|e| Error::ProviderUnavailable(e.to_string()))?;
+
+        let datum = match response.status() {
+            reqwest::StatusCode::OK => {
+                let TxByHash { cbor } = response
+                    .json::<TxByHash>()
+                    .await
+                    .map_err(|e| Error::ProviderUnavailable(e.to_string()))?;
+                let datum: PlutusData = hex::decode(&cbor)
+                    .map_err(|e| e.to_string())
+                    .and_then(|bytes| cbor::decode(&bytes).map_err(|e| e.to_string()))
+                    .map_err(|e| {
+                        Error::ProviderUnavailable(format!("malformed datum {datum_hash}: {e}"))
+                    })?;
+                Some(datum)
+            }
+            reqwest::StatusCode::NOT_FOUND => None,
+            status => {
+                return Err(Error::ProviderUnavailable(format!(
+                    "unexpected response from Blockfrost: {status}"
+                )))
+            }
+        };
+        self.timings.record("datum lookup", started);
+        Ok(datum)
+    }
+
+    async fn drep_vote(
+        &self,
+        script_hash: &Hash<28>,
+        proposal: &GovActionId,
+    ) -> Result<Option<Vote>, Error> {
+        self.online("drep vote lookup")?;
+
+        // NOTE: Governance endpoints aren't part of the Rust SDK (yet).
+        let started = Instant::now();
+        let mut latest = None;
+        for page in 1.. {
+            let response = self
+                .client
+                .get(format!(
+                    "https://cardano-{}.blockfrost.io/api/v0/governance/dreps/{}/votes?page={page}",
+                    self.network_prefix,
+                    drep_id(script_hash),
+                ))
+                .header("Accept", "application/json")
+                .header("project_id", self.project_id.expose())
+                .send()
+                .await
+                .map_err(|e| Error::ProviderUnavailable(e.to_string()))?;
+
+            // NOTE: A DRep that never voted (or was never registered) is reported as not found.
+            match response.status() {
+                reqwest::StatusCode::OK => {}
+                reqwest::StatusCode::NOT_FOUND => break,
+                status => {
+                    return Err(Error::ProviderUnavailable(format!(
+                        "unexpected response from Blockfrost: {status}"
+                    )))
+                }
+            }
+
+            let votes = response
+                .json::<Vec<DRepVote>>()
+                .await
+                .map_err(|e| Error::ProviderUnavailable(e.to_string()))?;
+            if votes.is_empty() {
+                break;
+            }
+
+            // Votes come in chronological order, and only the last one counts.
+            if let Some(vote) = votes.iter().rev().find(|vote| {
+                vote.proposal_tx_hash == proposal.transaction_id.to_string()
+                    && vote.proposal_cert_index == proposal.action_index
+            }) {
+                latest = match vote.vote.as_str() {
+                    "yes" => Some(Vote::Yes),
+                    "no" => Some(Vote::No),
+                    "abstain" => Some(Vote::Abstain),
+                    other => {
+                        return Err(Error::ProviderUnavailable(format!(
+                            "unexpected vote from Blockfrost: {other}"
+                        )))
+                    }
+                };
+            }
+        }
+        self.timings.record("drep vote lookup", started);
+        Ok(latest)
+    }
+
+    async fn is_registered(&self, stake_address: &StakeAddress) -> Result<bool, Error> {
+        self.online("stake registration lookup")?;
+
+        let started = Instant::now();
+        let registered = match self
+            .api
+            .accounts(stake_address.to_bech32().unwrap().as_str())
+            .await
+        {
+            Ok(account) => Ok(account.active),
+            Err(BlockfrostError::Response { reason, .. }) if reason.status_code == 404 => Ok(false),
+            Err(e) => Err(Error::ProviderUnavailable(e.to_string())),
+        };
+        self.timings.record("stake registration lookup", started);
+        registered
+    }
+
+    async fn tip(&self) -> Result<Tip, Error> {
+        self.online("tip")?;
+
+        let block = self
+            .api
+            .blocks_latest()
+            .await
+            .map_err(|e| Error::ProviderUnavailable(e.to_string()))?;
+        Ok(Tip {
+            slot: block.slot.expect("latest block without slot?") as u64,
+            epoch: block.epoch.expect("latest block without epoch?") as u64,
+            time: block.time as u64,
+        })
+    }
+
+    async fn epoch_boundary(&self, epoch: u64) -> Result<u64, Error> {
+        self.online("epoch boundary")?;
+
+        let started = Instant::now();
+        let unavailable = |e: BlockfrostError| Error::ProviderUnavailable(e.to_string());
+        let genesis = self.api.genesis().await.map_err(unavailable)?;
+        let current = self.api.epochs_latest().await.map_err(unavailable)?;
+        let tip = self.tip().await?;
+
+        let epoch_length = genesis.epoch_length as u64 * genesis.slot_length as u64;
+        let end_time = (current.end_time as u64).saturating_add(
+            epoch
+                .saturating_sub(current.epoch as u64)
+                .saturating_mul(epoch_length),
+        );
+
+        self.timings.record("epoch boundary", started);
+        Ok(tip
+            .slot
+            .saturating_add((end_time.saturating_sub(tip.time)) / genesis.slot_length as u64))
+    }
+
+    async fn proposal(&self, id: &GovActionId) -> Result<Option<Proposal>, Error> {
+        self.online("proposal lookup")?;
+
+        // NOTE: Governance endpoints aren't part of the Rust SDK (yet).
+        let started = Instant::now();
+        let response = self
+            .client
+            .get(format!(
+                "https://cardano-{}.blockfrost.io/api/v0/governance/proposals/{}/{}",
+                self.network_prefix, id.transaction_id, id.action_index
+            ))
+            .header("Accept", "application/json")
+            .header("project_id", self.project_id.expose())
+            .send()
+            .await
+            .map_err(|e| Error::ProviderUnavailable(e.to_string()))?;
+
+        let proposal = match response.status() {
+            reqwest::StatusCode::OK => response
+                .json::<Proposal>()
+                .await
+                .map(Some)
+                .map_err(|e| Error::ProviderUnavailable(e.to_string())),
+            reqwest::StatusCode::NOT_FOUND => Ok(None),
+            status => Err(Error::ProviderUnavailable(format!(
+                "unexpected response from Blockfrost: {status}"
+            ))),
+        };
+        self.timings.record("proposal lookup", started);
+        proposal
+    }
+
+    async fn resolve_many(
+        &self,
+        inputs: &[&TransactionInput],
+    ) -> Result<Vec<ResolvedInput>, Error> {
+        let started = Instant::now();
         let mut resolved = vec![];
         for i in inputs {
-            if let Some(r) = self.resolve(i).await {
-                resolved.push(r)
+            let input = || format!("{}#{}", i.transaction_id, i.index);
+            match self.resolve(i).await {
+                Some(r) => resolved.push(r),
+                None if self.resolved_inputs.is_some() => {
+                    return Err(Error::MissingResolvedInput { input: input() })
+                }
+                None => return Err(Error::InputNotFound { input: input() }),
+            }
+        }
+        self.timings.record("input resolution", started);
+        Ok(resolved)
+    }
+
+    async fn is_unspent(&self, input: &TransactionInput) -> Result<bool, Error> {
+        // NOTE: Offline, there's no telling; inputs missing from the file are caught on resolution.
+        if self.resolved_inputs.is_some() {
+            return Ok(true);
+        }
+
+        let tx_hash = hex::encode(input.transaction_id);
+
+        let not_found = || Error::InputNotFound {
+            input: format!("{}#{}", input.transaction_id, input.index),
+        };
+
+        let outputs = match self.api.transactions_utxos(tx_hash.as_str()).await {
+            Ok(utxo) => utxo.outputs,
+            Err(BlockfrostError::Response { reason, .. }) if reason.status_code == 404 => {
+                return Err(not_found())
             }
+            Err(e) => return Err(Error::ProviderUnavailable(e.to_string())),
+        };
+
+        let output = outputs
+            .into_iter()
+            .find(|o| !o.collateral && o.output_index == input.index as i32)
+            .ok_or_else(not_found)?;
+
+        // NOTE: An address without any UTxO left is reported as not found.
+        match self
+            .api
+            .addresses_utxos(&output.address, Pagination::all())
+            .await
+        {
+            Ok(utxos) => Ok(utxos
+                .iter()
+                .any(|u| u.tx_hash == tx_hash && u.output_index == input.index as i32)),
+            Err(BlockfrostError::Response { reason, .. }) if reason.status_code == 404 => Ok(false),
+            Err(e) => Err(Error::ProviderUnavailable(e.to_string())),
         }
-        resolved
     }
 
-    pub async fn resolve(&self, input: &TransactionInput) -> Option<ResolvedInput> {
+    async fn utxos_at(&self, address: &ShelleyAddress) -> Result<Vec<ResolvedInput>, Error> {
+        self.online("address lookup")?;
+
+        let started = Instant::now();
+        // NOTE: An address without any UTxO left is reported as not found.
+        let utxos = match self
+            .api
+            .addresses_utxos(&address.to_bech32().unwrap(), Pagination::all())
+            .await
+        {
+            Ok(utxos) => utxos,
+            Err(BlockfrostError::Response { reason, .. }) if reason.status_code == 404 => vec![],
+            Err(e) => return Err(Error::ProviderUnavailable(e.to_string())),
+        };
+
+        let utxos = utxos
+            .into_iter()
+            .map(|u| {
+                let (input, output) = from_address_utxo(u);
+                ResolvedInput {
+                    input,
+                    output: TransactionOutput::PostAlonzo(output),
+                }
+            })
+            .collect();
+        self.timings.record("address lookup", started);
+        Ok(utxos)
+    }
+
+    async fn asset_utxos(
+        &self,
+        policy: &Hash<28>,
+        name: &AssetName,
+    ) -> Result<Vec<(TransactionInput, PostAlonzoTransactionOutput)>, Error> {
+        self.online("asset lookup")?;
+
+        let started = Instant::now();
+        let asset = format!("{}{}", hex::encode(policy), hex::encode(&name[..]));
+
+        let addresses = match self.api.assets_addresses(&asset, Pagination::all()).await {
+            Ok(addresses) => addresses,
+            Err(BlockfrostError::Response { reason, .. }) if reason.status_code == 404 => vec![],
+            Err(e) => return Err(Error::ProviderUnavailable(e.to_string())),
+        };
+
+        let mut utxos = vec![];
+        for AssetAddressesInner { address, .. } in addresses {
+            utxos.extend(
+                self.api
+                    .addresses_utxos_asset(&address, &asset, Pagination::all())
+                    .await
+                    .map_err(|e| Error::ProviderUnavailable(e.to_string()))?
+                    .into_iter()
+                    .map(from_address_utxo),
+            );
+        }
+
+        self.timings.record(
+            format!("asset lookup ({:.8})", hex::encode(&name[..])),
+            started,
+        );
+        Ok(utxos)
+    }
+
+    async fn resolve(&self, input: &TransactionInput) -> Option<ResolvedInput> {
+        if let Some(ref resolved_inputs) = self.resolved_inputs {
+            return resolved_inputs.iter().find(|r| &r.input == input).cloned();
+        }
+
+        if let Some(resolved_input) = self.memo.resolved_inputs.borrow().get(input) {
+            return Some(resolved_input.clone());
+        }
+
         let utxo = self
             .api
             .transactions_utxos(hex::encode(input.transaction_id).as_str())
             .await
             .ok()?;
 
-        utxo.outputs
+        let resolved_input = utxo
+            .outputs
             .into_iter()
             .filter(|o| !o.collateral)
             .nth(input.index as usize)
@@ -225,29 +921,127 @@ impl Cardano {
                     "non-null reference script about to be ignored"
                 );
 
-                assert!(
-                    o.data_hash.is_none(),
-                    "non-null datum hash about to be ignored"
-                );
-
                 ResolvedInput {
                     input: input.clone(),
                     output: TransactionOutput::PostAlonzo(PostAlonzoTransactionOutput {
                         address: from_bech32(&o.address).into(),
                         value: from_tx_content_output_amounts(&o.amount[..]),
-                        datum_option: None,
+                        datum_option: from_datum(o.inline_datum.as_deref(), o.data_hash.as_deref()),
                         script_ref: None,
                     }),
                 }
-            })
+            })?;
+
+        self.memo
+            .resolved_inputs
+            .borrow_mut()
+            .insert(input.clone(), resolved_input.clone());
+        Some(resolved_input)
     }
 }
 
+// Every HTTP request goes through a client built here. Proxies are taken from the environment
+// (HTTPS_PROXY, HTTP_PROXY, ALL_PROXY and NO_PROXY, in either case). Certificate verification may
+// be disabled, which is only ever meant for self-signed hosts (e.g. anchors) during testing.
+pub fn http_client(insecure: bool) -> reqwest::Client {
+    if insecure {
+        eprintln!(
+            "warning: TLS certificate verification is DISABLED; connections may be intercepted."
+        );
+    }
+
+    reqwest::Client::builder()
+        .user_agent(concat!("zhuli/", env!("CARGO_PKG_VERSION")))
+        .danger_accept_invalid_certs(insecure)
+        .build()
+        .expect("failed to initialize HTTP client")
+}
+
 #[derive(serde::Serialize, serde::Deserialize, Debug)]
 struct TxByHash {
     cbor: String,
 }
 
+#[derive(Debug)]
+pub struct Tip {
+    pub slot: u64,
+    pub epoch: u64,
+    pub time: u64,
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub struct Proposal {
+    /// Last epoch during which the proposal can be voted on.
+    pub expiration: u64,
+    pub ratified_epoch: Option<u64>,
+    pub enacted_epoch: Option<u64>,
+    pub dropped_epoch: Option<u64>,
+    pub expired_epoch: Option<u64>,
+}
+
+impl Proposal {
+    pub fn is_active(&self) -> bool {
+        self.ratified_epoch.is_none()
+            && self.enacted_epoch.is_none()
+            && self.dropped_epoch.is_none()
+            && self.expired_epoch.is_none()
+    }
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct DRepVote {
+    proposal_tx_hash: String,
+    proposal_cert_index: u32,
+    vote: String,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct DRepById {
+    amount: String,
+}
+
+/// The DRep identifier of a script credential, as per CIP-0129.
+pub fn drep_id(script_hash: &Hash<28>) -> String {
+    const SCRIPT_DREP_HEADER: u8 = 0x23;
+    bech32::encode::<bech32::Bech32>(
+        bech32::Hrp::parse("drep").unwrap(),
+        &[&[SCRIPT_DREP_HEADER], script_hash.as_ref()].concat(),
+    )
+    .unwrap()
+}
+
+fn from_datum(inline_datum: Option<&str>, data_hash: Option<&str>) -> Option<DatumOption> {
+    match (inline_datum, data_hash) {
+        (Some(cbor), _) => Some(DatumOption::Data(CborWrap(
+            cbor::decode(&hex::decode(cbor).unwrap()).unwrap(),
+        ))),
+        (None, Some(hash)) => Some(DatumOption::Hash(hash.parse().unwrap())),
+        (None, None) => None,
+    }
+}
+
+fn from_address_utxo(
+    u: AddressUtxoContentInner,
+) -> (TransactionInput, PostAlonzoTransactionOutput) {
+    assert!(
+        u.reference_script_hash.is_none(),
+        "non-null reference script about to be ignored"
+    );
+
+    (
+        TransactionInput {
+            transaction_id: u.tx_hash.parse().unwrap(),
+            index: u.output_index as u64,
+        },
+        PostAlonzoTransactionOutput {
+            address: from_bech32(&u.address).into(),
+            value: from_tx_content_output_amounts(&u.amount[..]),
+            datum_option: from_datum(u.inline_datum.as_deref(), u.data_hash.as_deref()),
+            script_ref: None,
+        },
+    )
+}
+
 fn from_bech32(bech32: &str) -> Vec<u8> {
     bech32::decode(bech32).unwrap().1
 }
@@ -300,3 +1094,45 @@ fn from_tx_content_output_amounts(xs: &[TxContentOutputAmountInner]) -> Value {
         )
     }
 }
+
+fn bundled_cost_model_v3() -> Vec<i64> {
+    vec![
+        100788, 420, 1, 1, 1000, 173, 0, 1, 1000, 59957, 4, 1, 11183, 32, 201305, 8356, 4, 16000,
+        100, 16000, 100, 16000, 100, 16000, 100, 16000, 100, 16000, 100, 100, 100, 16000, 100,
+        94375, 32, 132994, 32, 61462, 4, 72010, 178, 0, 1, 22151, 32, 91189, 769, 4, 2, 85848,
+        123203, 7305, -900, 1716, 549, 57, 85848, 0, 1, 1, 1000, 42921, 4, 2, 24548, 29498, 38, 1,
+        898148, 27279, 1, 51775, 558, 1, 39184, 1000, 60594, 1, 141895, 32, 83150, 32, 15299, 32,
+        76049, 1, 13169, 4, 22100, 10, 28999, 74, 1, 28999, 74, 1, 43285, 552, 1, 44749, 541, 1,
+        33852, 32, 68246, 32, 72362, 32, 7243, 32, 7391, 32, 11546, 32, 85848, 123203, 7305, -900,
+        1716, 549, 57, 85848, 0, 1, 90434, 519, 0, 1, 74433, 32, 85848, 123203, 7305, -900, 1716,
+        549, 57, 85848, 0, 1, 1, 85848, 123203, 7305, -900, 1716, 549, 57, 85848, 0, 1, 955506,
+        213312, 0, 2, 270652, 22588, 4, 1457325, 64566, 4, 20467, 1, 4, 0, 141992, 32, 100788, 420,
+        1, 1, 81663, 32, 59498, 32, 20142, 32, 24588, 32, 20744, 32, 25933, 32, 24623, 32,
+        43053543, 10, 53384111, 14333, 10, 43574283, 26308, 10, 16000, 100, 16000, 100, 962335, 18,
+        2780678, 6, 442008, 1, 52538055, 3756, 18, 267929, 18, 76433006, 8868, 18, 52948122, 18,
+        1995836, 36, 3227919, 12, 901022, 1, 166917843, 4307, 36, 284546, 36, 158221314, 26549, 36,
+        74698472, 36, 333849714, 1, 254006273, 72, 2174038, 72, 2261318, 64571, 4, 207616, 8310, 4,
+        1293828, 28716, 63, 0, 1, 1006041, 43623, 251, 0, 1,
+    ]
+}
+
+// Blockfrost serves cost models as objects keyed by parameter index (or name, for older languages).
+// Only the former carries the order the ledger expects.
+fn cost_model_from_json(json: &serde_json::Value) -> Option<Vec<i64>> {
+    match json {
+        serde_json::Value::Array(values) => values.iter().map(|value| value.as_i64()).collect(),
+        serde_json::Value::Object(values) => {
+            let mut indexed = values
+                .iter()
+                .map(|(key, value)| Some((key.parse::<usize>().ok()?, value.as_i64()?)))
+                .collect::<Option<Vec<_>>>()?;
+            indexed.sort();
+            indexed
+                .into_iter()
+                .enumerate()
+                .map(|(ix, (key, value))| (ix == key).then_some(value))
+                .collect()
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,32 @@
+//  This Source Code Form is subject to the terms of the Mozilla Public
+//  License, v. 2.0. If a copy of the MPL was not distributed with this
+//  file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use crate::contract::build_rules;
+use clap::Command;
+use pallas_crypto::hash::Hash;
+use pallas_primitives::conway::AssetName;
+
+pub(crate) fn cmd() -> Command {
+    Command::new("asset-name")
+        .about("Compute the state token's asset name for a given delegate configuration.")
+        .after_help(color_print::cstr!(
+            r#"<underline><bold>Notes:</bold></underline>
+  1. This command doesn't require any network access.
+  2. The asset name is the one minted by <bold>delegate</bold> for the same <bold>--delegate</bold> and <bold>--quorum</bold>.
+
+<underline><bold>Example:</bold></underline>
+  <bold>asset-name</bold> \
+    <bold>--quorum</bold> 1 \
+    <bold>--delegate</bold> 000000000000000000000000000000000000000000000000000a11ce \
+    <bold>--delegate</bold> 00000000000000000000000000000000000000000000000000000b0b
+"#              ))
+        .arg(super::arg_delegate().required(true))
+        .arg(super::arg_quorum())
+        .arg(super::arg_token_prefix())
+}
+
+pub(crate) fn asset_name(delegates: Vec<Hash<28>>, quorum: usize, prefix: &[u8]) -> AssetName {
+    let (_, asset_name) = build_rules(&delegates[..], quorum, prefix);
+    asset_name
+}
@@ -0,0 +1,39 @@
+//  This Source Code Form is subject to the terms of the Mozilla Public
+//  License, v. 2.0. If a copy of the MPL was not distributed with this
+//  file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use clap::{value_parser, Arg, ArgAction, ArgMatches, Command};
+use clap_complete::Shell;
+use std::io;
+
+const ARG_SHELL: &str = "shell";
+
+pub(crate) fn cmd() -> Command {
+    Command::new("completions")
+        .about("Generate shell completions for all commands and options.")
+        .after_help(color_print::cstr!(
+            r#"<underline><bold>Example:</bold></underline>
+  <bold>completions</bold> bash > /etc/bash_completion.d/zhuli
+"#
+        ))
+        .arg(
+            Arg::new(ARG_SHELL)
+                .value_name("SHELL")
+                .required(true)
+                .value_parser(value_parser!(Shell))
+                .help("The shell to generate completions for.")
+                .action(ArgAction::Set),
+        )
+}
+
+pub(crate) fn get_arg_shell(args: &ArgMatches) -> Shell {
+    *args.get_one::<Shell>(ARG_SHELL).unwrap()
+}
+
+// Completions are derived from the very same definition used to parse arguments, so they can't
+// drift from the actual command-line.
+pub(crate) fn completions(shell: Shell) {
+    let mut cli = super::cli();
+    let name = cli.get_name().to_string();
+    clap_complete::generate(shell, &mut cli, name, &mut io::stdout());
+}
@@ -2,14 +2,15 @@
 //  License, v. 2.0. If a copy of the MPL was not distributed with this
 //  file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
-use crate::{cardano::Cardano, contract::*, pallas_extra::*};
+use crate::{backend::Backend, cardano::drep_id, contract::*, error::Error, pallas_extra::*};
 use clap::{ArgGroup, Command};
 use indoc::indoc;
+use pallas_addresses::{Address, StakeAddress, StakePayload};
 use pallas_codec::utils::{Bytes, NonZeroInt, Nullable, PositiveCoin, Set};
 use pallas_crypto::hash::Hash;
 use pallas_primitives::conway::{
-    Certificate, Language, PlutusV3Script, PostAlonzoTransactionOutput, PseudoTransactionOutput,
-    StakeCredential, TransactionBody, Tx, Value, WitnessSet,
+    Anchor, Certificate, DRep, PostAlonzoTransactionOutput, PseudoTransactionOutput,
+    StakeCredential, TransactionBody, TransactionInput, Tx, Value,
 };
 
 pub(crate) fn cmd() -> Command {
@@ -22,6 +23,13 @@ pub(crate) fn cmd() -> Command {
   1. The <bold>--contract</bold> option is only mandatory for re-delegation (as it typically doesn't exist otherwise).
   2. The specified <bold>--administrator</bold> must reflect the signatories for the transaction, but not necessarily ALL administrators.
      Only those authorizing the transaction must be present. And, there must be enough signatories for a quorum.
+     Omitting it altogether requires <bold>--allow-no-signers</bold>, as the resulting transaction can then be submitted by anyone.
+  3. Assets given with <bold>--asset</bold> are taken from the fuel and locked in the contract, alongside its state token. On
+     re-delegation, assets already held by the contract carry over to the new contract output.
+  4. With <bold>--datum</bold>, the contract output also carries its rules as datum. Contracts locked with a datum hash
+     remain spendable, provided the datum was once witnessed on-chain.
+  5. With <bold>--with-stake</bold>, the stake key of the given UTxO's address also delegates to the new DRep (registering it if
+     needed). The transaction must then be signed by that stake key as well.
 
 <underline><bold>Examples:</bold></underline>
 <italic>1. No previous contract instance, defining a 1-of-2 hot delegate: </italic>
@@ -47,8 +55,55 @@ pub(crate) fn cmd() -> Command {
     .arg(super::arg_quorum())
     .arg(super::arg_validator())
     .arg(super::arg_contract(false))
-    .arg(super::arg_administrator())
+    .arg(super::arg_administrator().required(false))
+    .arg(super::flag_allow_no_signers())
+    .arg(super::arg_anchor().short(None))
+    .arg(super::arg_anchor_hash())
+    .arg(super::arg_anchor_file())
+    .arg(super::flag_insecure())
     .arg(super::arg_fuel())
+    .arg(super::arg_change_address())
+    .arg(super::arg_keep_change_above())
+    .arg(super::arg_donation())
+    .arg(super::flag_collateral_only_ada())
+    .arg(super::flag_no_collateral_return())
+    .arg(super::arg_collateral_percent())
+    .arg(super::arg_asset())
+    .arg(super::arg_datum())
+    .arg(super::arg_token_prefix())
+    .arg(super::arg_with_stake())
+    .arg(super::arg_fee_constant())
+    .arg(super::arg_fee_coefficient())
+    .arg(super::arg_price_mem())
+    .arg(super::arg_price_steps())
+    .arg(super::flag_dump_fee_model())
+    .arg(super::arg_max_fee())
+    .arg(super::arg_assume_yes_convergence())
+    .arg(super::arg_redeemer())
+    .arg(super::arg_bundle())
+    .arg(super::arg_plan())
+    .arg(super::arg_debug_attempts())
+    .arg(super::arg_max_build_attempts())
+    .arg(super::arg_expected_signers())
+    .arg(super::flag_double_check_eval())
+    .arg(super::arg_epoch())
+    .arg(super::arg_protocol_params())
+    .arg(super::arg_era())
+    .arg(super::arg_encoding())
+    .arg(super::arg_out())
+    .arg(super::arg_dump_resolved_inputs())
+    .arg(super::arg_resolved_inputs())
+    .arg(super::arg_network())
+    .arg(super::arg_simulate_fee_bump())
+    .arg(super::flag_best_effort())
+    .arg(super::flag_explain())
+    .arg(super::flag_timings())
+    .arg(super::flag_abort_on_warning())
+    .arg(super::flag_verify_on_chain_hash())
+    .arg(super::arg_validity_start())
+    .arg(super::arg_ttl())
+    .arg(super::arg_valid_for())
+    .arg(super::arg_valid_for_epochs())
     .group(ArgGroup::new("source")
         .args(["contract", "validator"])
         .multiple(false)
@@ -56,89 +111,182 @@ pub(crate) fn cmd() -> Command {
     )
 }
 
+#[allow(clippy::too_many_arguments)]
 pub(crate) async fn delegate(
-    network: Cardano,
+    network: &impl Backend,
     delegates: Vec<Hash<28>>,
     quorum: usize,
     administrators: Vec<Hash<28>>,
+    anchor: Option<Anchor>,
     validator: Bytes,
-    OutputReference(fuel): OutputReference,
-) -> Tx {
+    fuels: Vec<OutputReference>,
+    assets: Vec<AssetQuantity>,
+    datum: DatumMode,
+    with_stake: Option<OutputReference>,
+    options: &BuildOptions,
+) -> Result<Tx, Error> {
     let (validator_hash, validator_address) =
         from_validator(validator.as_ref(), network.network_id());
 
-    let params = network.protocol_parameters().await;
+    let fuels = fuels
+        .into_iter()
+        .map(|OutputReference(fuel)| fuel)
+        .collect::<Vec<_>>();
+    let fuel = fuels[0].clone();
 
-    let resolved_inputs = network.resolve_many(&[&fuel]).await;
+    for fuel in fuels.iter() {
+        super::ensure_unspent_fuel(network, fuel).await?;
+    }
+
+    let params = network.protocol_parameters().await?;
+
+    let resolved_inputs = network
+        .resolve_many(&fuels.iter().collect::<Vec<_>>())
+        .await?;
     let fuel_output = expect_post_alonzo(&resolved_inputs[0].output);
+    let change_address = super::change_address(network, options, &fuel_output.address)?;
+
+    // NOTE: The stake source is only looked at, not spent. We only support key-based stake
+    // credentials, as scripts would require a redeemer we know nothing about.
+    let stake = match with_stake {
+        None => None,
+        Some(OutputReference(source)) => {
+            let no_stake_key = || Error::StakeSourceWithoutStakeKey {
+                utxo: OutputReference(source.clone()).to_string(),
+            };
+
+            let source_output = network.resolve(&source).await.ok_or_else(no_stake_key)?;
+
+            let stake_address =
+                match Address::from_bytes(&expect_post_alonzo(&source_output.output).address[..]) {
+                    Ok(Address::Shelley(address)) => StakeAddress::try_from(address).ok(),
+                    _ => None,
+                }
+                .ok_or_else(no_stake_key)?;
+
+            let stake_key = match stake_address.payload() {
+                StakePayload::Stake(key_hash) => *key_hash,
+                StakePayload::Script(_) => return Err(no_stake_key()),
+            };
+
+            let is_registered = network.is_registered(&stake_address).await?;
+
+            Some((stake_key, is_registered))
+        }
+    };
+
+    super::explain(options, || {
+        serde_json::json!({
+            "authorizedBy": "administrators",
+            "signatories": administrators.iter().map(|h| h.to_string()).collect::<Vec<_>>(),
+            "drepId": drep_id(&validator_hash),
+            "newRules": {
+                "delegates": delegates.iter().map(|h| h.to_string()).collect::<Vec<_>>(),
+                "quorum": quorum,
+            },
+        })
+    });
 
     build_transaction(
         &BuildParams::from(&params),
+        options,
         &resolved_inputs[..],
         |fee, ex_units| {
-            let (rules, asset_name) = build_rules(&delegates[..], quorum);
+            let (rules, asset_name) = build_rules(&delegates[..], quorum, &options.token_prefix);
+
+            let contract_assets = singleton_assets(
+                validator_hash,
+                &[(asset_name.clone(), PositiveCoin::try_from(1).unwrap())],
+            )?;
 
             let contract_output =
                 new_min_value_output(params.min_utxo_deposit_coefficient, |lovelace| {
                     PostAlonzoTransactionOutput {
                         address: validator_address.to_vec().into(),
-                        value: Value::Multiasset(
-                            lovelace,
-                            singleton_assets(
-                                validator_hash,
-                                &[(asset_name.clone(), PositiveCoin::try_from(1).unwrap())],
-                            ),
+                        value: value_add_assets(
+                            Value::Multiasset(lovelace, contract_assets.clone()),
+                            &assets,
                         ),
-                        datum_option: None,
+                        datum_option: datum.datum_option(&rules),
                         script_ref: None,
                     }
                 });
 
-            let total_collateral = (fee as f64 * params.collateral_percent).ceil() as u64;
-
             let mut redeemers = vec![];
 
-            let inputs = vec![fuel.clone()];
+            let stake_deposit = match stake {
+                Some((_, false)) => params.stake_deposit,
+                _ => 0,
+            };
+
+            let donation = options.donation.map(u64::from).unwrap_or(0);
+
+            let total_cost = params.drep_deposit
+                + stake_deposit
+                + lovelace_of(&contract_output.value)
+                + fee
+                + donation;
 
-            let total_cost = params.drep_deposit + lovelace_of(&contract_output.value) + fee;
+            let (inputs, fuel_value) =
+                select_fuel(&resolved_inputs[..fuels.len()], total_cost, &assets);
+            let fuel_value = take_assets(&fuel, fuel_value, &assets)?;
 
-            let outputs = vec![
+            let mut outputs = vec![
                 // Contract
                 contract_output,
                 // Change
                 PostAlonzoTransactionOutput {
-                    address: fuel_output.address.clone(),
-                    value: value_subtract_lovelace(fuel_output.value.clone(), total_cost)
-                        .expect("not enough fuel"),
+                    address: change_address.clone(),
+                    value: change_value(fuel_value.clone(), total_cost)?,
                     datum_option: None,
                     script_ref: None,
                 },
             ];
 
-            let collateral_return = PostAlonzoTransactionOutput {
-                address: fuel_output.address.clone(),
-                value: value_subtract_lovelace(fuel_output.value.clone(), total_collateral)
-                    .expect("not enough fuel"),
-                datum_option: None,
-                script_ref: None,
-            };
+            let fee = fold_dust_change(
+                &mut outputs,
+                1,
+                fee,
+                params.min_utxo_deposit_coefficient,
+                options.keep_change_above,
+            );
+
+            let total_collateral = total_collateral(fee, params.collateral_percent);
+
+            let collateral_return = collateral_return(&fuel, fuel_output, total_collateral)?;
+
+            let datums = non_empty_set(datum.witnesses(&rules));
 
             let mint = singleton_assets(
                 validator_hash,
                 &[(asset_name, NonZeroInt::try_from(1).unwrap())],
-            );
+            )?;
             redeemers.push(Redeemer::mint(0, void(), ex_units[0]));
 
-            let certificates = vec![Certificate::RegDRepCert(
+            let mut certificates = vec![Certificate::RegDRepCert(
                 StakeCredential::Scripthash(validator_hash),
                 params.drep_deposit,
-                Nullable::Null,
+                anchor.clone().into(),
             )];
             redeemers.push(Redeemer::publish(0, rules, ex_units[1]));
 
+            // NOTE: Key-based stake credentials are witnessed by signatures, so there's no
+            // redeemer to go along with the certificate.
+            let mut required_signers = administrators.clone();
+            if let Some((stake_key, is_registered)) = stake {
+                let credential = StakeCredential::AddrKeyhash(stake_key);
+                let drep = DRep::Script(validator_hash);
+                certificates.push(if is_registered {
+                    Certificate::VoteDeleg(credential, drep)
+                } else {
+                    Certificate::VoteRegDeleg(credential, drep, params.stake_deposit)
+                });
+                required_signers.push(stake_key);
+            }
+
             // ----- Put it all together
-            let redeemers = non_empty_pairs(redeemers).unwrap();
-            Tx {
+            let redeemers = required_pairs("redeemers", options.override_redeemers(redeemers))?;
+            Ok(Tx {
                 transaction_body: TransactionBody {
                     inputs: Set::from(inputs),
                     network_id: Some(from_network(network.network_id())),
@@ -146,113 +294,197 @@ pub(crate) async fn delegate(
                     mint: Some(mint),
                     certificates: non_empty_set(certificates),
                     fee,
+                    donation: options.donation,
                     collateral: non_empty_set(vec![fuel.clone()]),
                     collateral_return: Some(PseudoTransactionOutput::PostAlonzo(collateral_return)),
                     total_collateral: Some(total_collateral),
-                    required_signers: non_empty_set(administrators.clone()),
+                    required_signers: non_empty_set(required_signers),
                     script_data_hash: Some(
                         script_integrity_hash(
                             Some(&redeemers),
-                            None,
-                            &[(Language::PlutusV3, &params.cost_model_v3[..])],
+                            datums.as_ref(),
+                            &[(options.era.plutus_language(), &params.cost_model_v3[..])],
                         )
                         .unwrap(),
                     ),
                     ..default_transaction_body()
                 },
-                transaction_witness_set: WitnessSet {
-                    redeemer: Some(redeemers.into()),
-                    plutus_v3_script: non_empty_set(vec![PlutusV3Script(validator.clone())]),
-                    ..default_witness_set()
-                },
+                transaction_witness_set: new_witness_set(redeemers, datums, &[&validator]),
                 success: true,
                 auxiliary_data: Nullable::Null,
-            }
+            })
         },
     )
 }
 
+#[allow(clippy::too_many_arguments)]
 pub(crate) async fn redelegate(
-    network: Cardano,
+    network: &impl Backend,
     delegates: Vec<Hash<28>>,
     quorum: usize,
     administrators: Vec<Hash<28>>,
+    anchor: Option<Anchor>,
     OutputReference(contract): OutputReference,
-    OutputReference(fuel): OutputReference,
-) -> Tx {
+    fuels: Vec<OutputReference>,
+    assets: Vec<AssetQuantity>,
+    datum: DatumMode,
+    options: &BuildOptions,
+) -> Result<Tx, Error> {
     let (validator, validator_hash, validator_address) =
-        recover_validator(&network, &contract.transaction_id).await;
+        recover_validator(network, &contract.transaction_id).await?;
 
-    let params = network.protocol_parameters().await;
+    let fuels = fuels
+        .into_iter()
+        .map(|OutputReference(fuel)| fuel)
+        .collect::<Vec<_>>();
+    let fuel = fuels[0].clone();
 
-    let resolved_inputs = network.resolve_many(&[&fuel, &contract]).await;
+    for fuel in fuels.iter() {
+        super::ensure_unspent_fuel(network, fuel).await?;
+    }
+
+    let params = network.protocol_parameters().await?;
+
+    let resolved_inputs = network
+        .resolve_many(&fuels.iter().chain([&contract]).collect::<Vec<_>>())
+        .await?;
     let fuel_output = expect_post_alonzo(&resolved_inputs[0].output);
-    let contract_old_output = expect_post_alonzo(&resolved_inputs[1].output);
+    let change_address = super::change_address(network, options, &fuel_output.address)?;
+    let contract_old_output = expect_post_alonzo(&resolved_inputs[fuels.len()].output);
+
+    if options.verify_on_chain_hash {
+        ensure_validator_address(&contract, &validator_address, &contract_old_output.address)?;
+    }
+
+    let old_asset_name = expect_contract_token(
+        &contract,
+        &contract_old_output.value,
+        &validator_hash,
+        &options.token_prefix,
+    )?;
+    let spent_datums = spent_datums(network, contract_old_output).await?;
+
+    let mut custody = assets_except(&contract_old_output.value, &validator_hash, &old_asset_name);
+    custody.extend(assets.iter().cloned());
+
+    // NOTE: Re-delegating is authorized by administrators, which are baked into the validator
+    // itself. So the old rules aren't needed to build the transaction; only to show what changes.
+    let old_rules = if options.explain {
+        let (rules, _) = recover_rules(
+            network,
+            &validator_hash,
+            &contract,
+            &contract_old_output.value,
+            &options.token_prefix,
+        )
+        .await?;
+        parse_rules(&rules)
+    } else {
+        None
+    };
+
+    super::explain(options, || {
+        let new_rules = Rules {
+            delegates: delegates.clone(),
+            quorum,
+        };
+        serde_json::json!({
+            "authorizedBy": "administrators",
+            "signatories": administrators.iter().map(|h| h.to_string()).collect::<Vec<_>>(),
+            "drepId": drep_id(&validator_hash),
+            "oldRules": old_rules.as_ref().map(|rules| serde_json::json!({
+                "delegates": rules.delegates.iter().map(|h| h.to_string()).collect::<Vec<_>>(),
+                "quorum": rules.quorum,
+            })),
+            "newRules": {
+                "delegates": delegates.iter().map(|h| h.to_string()).collect::<Vec<_>>(),
+                "quorum": quorum,
+            },
+            "changes": old_rules.as_ref().map(|rules| rules.diff(&new_rules)),
+        })
+    });
 
     build_transaction(
         &BuildParams::from(&params),
+        options,
         &resolved_inputs[..],
         |fee, ex_units| {
-            let (rules, new_asset_name) = build_rules(&delegates[..], quorum);
+            let (rules, new_asset_name) =
+                build_rules(&delegates[..], quorum, &options.token_prefix);
 
-            let old_asset_name = find_contract_token(&contract_old_output.value)
-                .expect("no state token in contract utxo?");
+            let contract_assets = singleton_assets(
+                validator_hash,
+                &[(new_asset_name.clone(), PositiveCoin::try_from(1).unwrap())],
+            )?;
 
             let contract_new_output =
                 new_min_value_output(params.min_utxo_deposit_coefficient, |lovelace| {
                     PostAlonzoTransactionOutput {
                         address: validator_address.to_vec().into(),
-                        value: Value::Multiasset(
-                            lovelace,
-                            singleton_assets(
-                                validator_hash,
-                                &[(new_asset_name.clone(), PositiveCoin::try_from(1).unwrap())],
-                            ),
+                        value: value_add_assets(
+                            Value::Multiasset(lovelace, contract_assets.clone()),
+                            &custody,
                         ),
-                        datum_option: None,
+                        datum_option: datum.datum_option(&rules),
                         script_ref: None,
                     }
                 });
 
-            let total_collateral = (fee as f64 * params.collateral_percent).ceil() as u64;
-
             let mut redeemers = vec![];
 
-            let mut inputs = vec![contract.clone(), fuel.clone()];
-            inputs.sort();
+            let donation = options.donation.map(u64::from).unwrap_or(0);
 
-            let total_cost = lovelace_of(&contract_new_output.value) + fee
+            let total_cost = lovelace_of(&contract_new_output.value) + fee + donation
                 - lovelace_of(&contract_old_output.value);
 
+            let (fuel_inputs, fuel_value) =
+                select_fuel(&resolved_inputs[..fuels.len()], total_cost, &assets);
+            let fuel_value = take_assets(&fuel, fuel_value, &assets)?;
+
+            let mut inputs = vec![contract.clone()];
+            inputs.extend(fuel_inputs);
+            inputs.sort();
+
+            let mut datums = spent_datums.clone();
+            for witness in datum.witnesses(&rules) {
+                if !datums.contains(&witness) {
+                    datums.push(witness);
+                }
+            }
+            let datums = non_empty_set(datums);
+
             let mint = singleton_assets(
                 validator_hash,
                 &[
                     (new_asset_name, NonZeroInt::try_from(1).unwrap()),
-                    (old_asset_name, NonZeroInt::try_from(-1).unwrap()),
+                    (old_asset_name.clone(), NonZeroInt::try_from(-1).unwrap()),
                 ],
-            );
+            )?;
             redeemers.push(Redeemer::mint(0, void(), ex_units[0]));
 
-            let outputs = vec![
+            let mut outputs = vec![
                 // Contract
                 contract_new_output,
                 // Change
                 PostAlonzoTransactionOutput {
-                    address: fuel_output.address.clone(),
-                    value: value_subtract_lovelace(fuel_output.value.clone(), total_cost)
-                        .expect("not enough fuel"),
+                    address: change_address.clone(),
+                    value: change_value(fuel_value.clone(), total_cost)?,
                     datum_option: None,
                     script_ref: None,
                 },
             ];
 
-            let collateral_return = PostAlonzoTransactionOutput {
-                address: fuel_output.address.clone(),
-                value: value_subtract_lovelace(fuel_output.value.clone(), total_collateral)
-                    .expect("not enough fuel"),
-                datum_option: None,
-                script_ref: None,
-            };
+            let fee = fold_dust_change(
+                &mut outputs,
+                1,
+                fee,
+                params.min_utxo_deposit_coefficient,
+                options.keep_change_above,
+            );
+
+            let total_collateral = total_collateral(fee, params.collateral_percent);
+
+            let collateral_return = collateral_return(&fuel, fuel_output, total_collateral)?;
 
             redeemers.push(Redeemer::spend(
                 (&inputs[..], &contract),
@@ -268,15 +500,15 @@ pub(crate) async fn redelegate(
                 Certificate::RegDRepCert(
                     StakeCredential::Scripthash(validator_hash),
                     params.drep_deposit,
-                    Nullable::Null,
+                    anchor.clone().into(),
                 ),
             ];
             redeemers.push(Redeemer::publish(0, void(), ex_units[2]));
             redeemers.push(Redeemer::publish(1, rules, ex_units[3]));
 
             // ----- Put it all together
-            let redeemers = non_empty_pairs(redeemers).unwrap();
-            Tx {
+            let redeemers = required_pairs("redeemers", options.override_redeemers(redeemers))?;
+            Ok(Tx {
                 transaction_body: TransactionBody {
                     inputs: Set::from(inputs),
                     network_id: Some(from_network(network.network_id())),
@@ -284,6 +516,7 @@ pub(crate) async fn redelegate(
                     mint: Some(mint),
                     certificates: non_empty_set(certificates),
                     fee,
+                    donation: options.donation,
                     collateral: non_empty_set(vec![fuel.clone()]),
                     collateral_return: Some(PseudoTransactionOutput::PostAlonzo(collateral_return)),
                     total_collateral: Some(total_collateral),
@@ -291,21 +524,36 @@ pub(crate) async fn redelegate(
                     script_data_hash: Some(
                         script_integrity_hash(
                             Some(&redeemers),
-                            None,
-                            &[(Language::PlutusV3, &params.cost_model_v3[..])],
+                            datums.as_ref(),
+                            &[(options.era.plutus_language(), &params.cost_model_v3[..])],
                         )
                         .unwrap(),
                     ),
                     ..default_transaction_body()
                 },
-                transaction_witness_set: WitnessSet {
-                    redeemer: Some(redeemers.into()),
-                    plutus_v3_script: non_empty_set(vec![PlutusV3Script(validator.clone())]),
-                    ..default_witness_set()
-                },
+                transaction_witness_set: new_witness_set(redeemers, datums, &[&validator]),
                 success: true,
                 auxiliary_data: Nullable::Null,
-            }
+            })
         },
     )
 }
+
+// Assets to lock in the contract come from the fuel; whatever else it holds goes back as change.
+fn take_assets(
+    fuel: &TransactionInput,
+    mut value: Value,
+    assets: &[AssetQuantity],
+) -> Result<Value, Error> {
+    for asset in assets {
+        let available = quantity_of(&value, &asset.policy, &asset.name);
+        value = value_subtract_assets(value, std::slice::from_ref(asset)).ok_or_else(|| {
+            Error::InsufficientFuelAssets {
+                fuel: OutputReference(fuel.clone()).to_string(),
+                asset: asset.to_string(),
+                available,
+            }
+        })?;
+    }
+    Ok(value)
+}
@@ -0,0 +1,105 @@
+//  This Source Code Form is subject to the terms of the Mozilla Public
+//  License, v. 2.0. If a copy of the MPL was not distributed with this
+//  file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use crate::error::Error;
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use indoc::formatdoc;
+use pallas_crypto::{
+    hash::{Hash, Hasher},
+    key::ed25519::SecretKey,
+};
+use rand_core::{OsRng, RngCore};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+const ARG_SIGNING_KEY_FILE: &str = "signing-key-file";
+
+const ARG_VERIFICATION_KEY_FILE: &str = "verification-key-file";
+
+pub(crate) fn cmd() -> Command {
+    Command::new("generate-key")
+        .about("Generate a new ed25519 key pair, for testing purposes.")
+        .after_help(color_print::cstr!(
+            r#"<underline><bold>Notes:</bold></underline>
+  1. Keys are written as text envelopes, compatible with the <italic>cardano-cli</italic>.
+  2. The key hash digest (blake2b-228) of the verification key is printed on success.
+
+<underline><bold>Example:</bold></underline>
+  <bold>generate-key</bold> \
+    <bold>--signing-key-file</bold> delegate.sk \
+    <bold>--verification-key-file</bold> delegate.vk
+"#
+        ))
+        .arg(
+            Arg::new(ARG_SIGNING_KEY_FILE)
+                .long(ARG_SIGNING_KEY_FILE)
+                .value_name("FILEPATH")
+                .required(true)
+                .help("Destination of the generated signing key.")
+                .action(ArgAction::Set),
+        )
+        .arg(
+            Arg::new(ARG_VERIFICATION_KEY_FILE)
+                .long(ARG_VERIFICATION_KEY_FILE)
+                .value_name("FILEPATH")
+                .required(true)
+                .help("Destination of the generated verification key.")
+                .action(ArgAction::Set),
+        )
+}
+
+pub(crate) fn get_arg_key_files(args: &ArgMatches) -> (PathBuf, PathBuf) {
+    (
+        PathBuf::from(args.get_one::<String>(ARG_SIGNING_KEY_FILE).unwrap()),
+        PathBuf::from(args.get_one::<String>(ARG_VERIFICATION_KEY_FILE).unwrap()),
+    )
+}
+
+pub(crate) fn generate_key(
+    signing_key_file: &Path,
+    verification_key_file: &Path,
+) -> Result<Hash<28>, Error> {
+    let mut secret = [0; SecretKey::SIZE];
+    OsRng.fill_bytes(&mut secret);
+    let signing_key = SecretKey::from(secret);
+    let verification_key = signing_key.public_key();
+
+    write_text_envelope(
+        signing_key_file,
+        "PaymentSigningKeyShelley_ed25519",
+        "Payment Signing Key",
+        &secret,
+    )?;
+
+    write_text_envelope(
+        verification_key_file,
+        "PaymentVerificationKeyShelley_ed25519",
+        "Payment Verification Key",
+        verification_key.as_ref(),
+    )?;
+
+    Ok(Hasher::<224>::hash(verification_key.as_ref()))
+}
+
+fn write_text_envelope(
+    path: &Path,
+    type_: &str,
+    description: &str,
+    key: &[u8],
+) -> Result<(), Error> {
+    // Keys are CBOR-serialised as plain bytestrings of 32 bytes (i.e. 0x5820 + key).
+    let envelope = formatdoc! {
+        r#"{{
+          "type": "{type_}",
+          "description": "{description}",
+          "cborHex": "5820{}"
+        }}
+        "#,
+        hex::encode(key),
+    };
+
+    fs::write(path, envelope).map_err(|e| Error::FailedToWriteFile(path.to_path_buf(), e))
+}
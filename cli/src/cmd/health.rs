@@ -0,0 +1,80 @@
+//  This Source Code Form is subject to the terms of the Mozilla Public
+//  License, v. 2.0. If a copy of the MPL was not distributed with this
+//  file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use crate::{
+    backend::Backend,
+    cardano::{Cardano, Tip},
+    error::Error,
+};
+use clap::Command;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// Blocks are produced every 20 seconds on average, but gaps of a few minutes aren't unusual. Past
+// that, the provider is most likely lagging behind.
+const MAX_LAG: Duration = Duration::from_secs(300);
+
+pub(crate) fn cmd() -> Command {
+    Command::new("health")
+        .about("Check that the provider is reachable and in sync with the network.")
+        .after_help(color_print::cstr!(
+            r#"<underline><bold>Notes:</bold></underline>
+  1. The provider is deemed in sync when its latest block is less than 5 minutes old, as per the local clock.
+  2. Latencies are round-trips as seen from this machine, and include any proxy in-between.
+
+<underline><bold>Example:</bold></underline>
+  <bold>health</bold>
+"#
+        ))
+}
+
+#[derive(Debug)]
+pub(crate) struct Health {
+    network: String,
+    tip: Tip,
+    lag: Duration,
+    tip_latency: Duration,
+    protocol_parameters_latency: Duration,
+}
+
+impl Health {
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "network": self.network,
+            "tip": {
+                "slot": self.tip.slot,
+                "epoch": self.tip.epoch,
+                "time": self.tip.time,
+            },
+            "lagSeconds": self.lag.as_secs(),
+            "synced": self.lag <= MAX_LAG,
+            "latencyMs": {
+                "tip": self.tip_latency.as_millis() as u64,
+                "protocolParameters": self.protocol_parameters_latency.as_millis() as u64,
+            },
+        })
+    }
+}
+
+pub(crate) async fn health(network: Cardano) -> Result<Health, Error> {
+    let started = Instant::now();
+    let tip = network.tip().await?;
+    let tip_latency = started.elapsed();
+
+    let started = Instant::now();
+    network.protocol_parameters().await?;
+    let protocol_parameters_latency = started.elapsed();
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("system clock before 1970?")
+        .as_secs();
+
+    Ok(Health {
+        network: network.network_name().to_string(),
+        lag: Duration::from_secs(now.saturating_sub(tip.time)),
+        tip,
+        tip_latency,
+        protocol_parameters_latency,
+    })
+}
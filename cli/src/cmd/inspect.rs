@@ -0,0 +1,388 @@
+//  This Source Code Form is subject to the terms of the Mozilla Public
+//  License, v. 2.0. If a copy of the MPL was not distributed with this
+//  file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use crate::{
+    backend::Backend,
+    cardano::{drep_id, Cardano},
+    error::Error,
+    pallas_extra::{
+        body_hash, raw_script_integrity_hash, raw_witness_set_field, Era, OutputReference,
+    },
+};
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use pallas_addresses::Address;
+use pallas_codec::{minicbor as cbor, utils::Nullable};
+use pallas_crypto::hash::Hash;
+use pallas_primitives::{
+    alonzo,
+    conway::{
+        Anchor, Certificate, DRep, DatumOption, MintedTx, StakeCredential, TransactionInput,
+        TransactionOutput, Tx, Value, Vote, Voter,
+    },
+};
+use std::path::PathBuf;
+
+pub(crate) fn cmd() -> Command {
+    Command::new("inspect")
+        .about("Decode a transaction and summarize what it does, e.g. to review it before signing.")
+        .after_help(color_print::cstr!(
+            r#"<underline><bold>Notes:</bold></underline>
+  1. Inputs are shown as references only, as they aren't resolved. No network access is needed, unless for
+     fetching a cost model (see 4.).
+  2. Hashes and asset names are hex-encoded; addresses are bech32-encoded; quantities are in lovelace.
+  3. The transaction id is recomputed from the body, as it would be on-chain.
+  4. With <bold>--verify-script-integrity</bold>, the script integrity hash is recomputed from the redeemers, datums and
+     cost model, and compared to the one in the body; a mismatch is an error. The cost model is taken from
+     <bold>--protocol-params</bold> when given (for <bold>--network</bold>, when keyed by network), or else fetched from the network.
+
+<underline><bold>Example:</bold></underline>
+  <bold>inspect</bold> <bold>--tx</bold> vote.tx <bold>--verify-script-integrity</bold>
+"#
+        ))
+        .arg(super::sign::arg_tx())
+        .arg(
+            Arg::new(FLAG_VERIFY_SCRIPT_INTEGRITY)
+                .long(FLAG_VERIFY_SCRIPT_INTEGRITY)
+                .help("Recompute the script integrity hash, and check it against the one in the body.")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(super::arg_protocol_params())
+        .arg(super::arg_epoch())
+        .arg(super::arg_network())
+}
+
+const FLAG_VERIFY_SCRIPT_INTEGRITY: &str = "verify-script-integrity";
+
+// The cost model to verify the script integrity hash against, if asked to.
+pub(crate) async fn get_arg_cost_model(args: &ArgMatches) -> Result<Option<Vec<i64>>, Error> {
+    if !args.get_flag(FLAG_VERIFY_SCRIPT_INTEGRITY) {
+        return Ok(None);
+    }
+
+    let network = super::get_arg_network(args);
+
+    let params = match super::get_arg_protocol_params(args, network)? {
+        Some(params) => params,
+        None => {
+            match network {
+                Some(network) => Cardano::new_on(network)?,
+                None => Cardano::new()?,
+            }
+            .with_epoch(super::get_arg_epoch(args)?)
+            .protocol_parameters()
+            .await?
+        }
+    };
+
+    Ok(Some(params.cost_model_v3))
+}
+
+#[derive(Debug)]
+pub(crate) struct Inspection {
+    summary: serde_json::Value,
+    script_integrity: Option<ScriptIntegrity>,
+}
+
+#[derive(Debug)]
+struct ScriptIntegrity {
+    expected: Option<Hash<32>>,
+    actual: Option<Hash<32>>,
+}
+
+impl Inspection {
+    pub fn to_json(&self) -> serde_json::Value {
+        let mut json = self.summary.clone();
+        if let Some(ScriptIntegrity { expected, actual }) = self.script_integrity {
+            json["scriptIntegrity"] = serde_json::json!({
+                "expected": expected.map(|hash| hash.to_string()),
+                "actual": actual.map(|hash| hash.to_string()),
+                "valid": expected == actual,
+            });
+        }
+        json
+    }
+
+    pub fn ensure_script_integrity(&self) -> Result<(), Error> {
+        match self.script_integrity {
+            Some(ScriptIntegrity { expected, actual }) if expected != actual => {
+                let show = |hash: Option<Hash<32>>| {
+                    hash.map(|hash| hash.to_string())
+                        .unwrap_or_else(|| "none".to_string())
+                };
+                Err(Error::ScriptIntegrityMismatch {
+                    expected: show(expected),
+                    actual: show(actual),
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+pub(crate) fn inspect(tx: &[u8], cost_model: Option<&[i64]>) -> Result<Inspection, Error> {
+    let malformed =
+        |e: cbor::decode::Error| Error::MalformedTransaction(PathBuf::from("tx"), e.to_string());
+
+    let minted_tx: MintedTx = cbor::decode(tx).map_err(malformed)?;
+    let id = body_hash(&minted_tx);
+
+    // NOTE: The script integrity hash is over the original redeemers and datums too; the latter being taken straight from the witness set, as
+    // Pallas only keeps the original bytes of each datum, not those of the set.
+    let script_integrity = cost_model.map(|cost_model| {
+        let witness_set = &minted_tx.transaction_witness_set;
+        let redeemers = witness_set.redeemer.as_ref().map(|r| r.raw_cbor());
+        let datums = raw_witness_set_field(witness_set.raw_cbor(), 4);
+        ScriptIntegrity {
+            expected: if redeemers.is_none() && datums.is_none() {
+                None
+            } else {
+                raw_script_integrity_hash(
+                    redeemers,
+                    datums,
+                    &[(Era::default().plutus_language(), cost_model)],
+                )
+            },
+            actual: minted_tx.transaction_body.script_data_hash,
+        }
+    });
+
+    let Tx {
+        transaction_body: body,
+        ..
+    } = cbor::decode(tx).map_err(malformed)?;
+
+    let summary = serde_json::json!({
+        "id": id.to_string(),
+        "inputs": inputs_to_json(body.inputs.iter()),
+        "referenceInputs": inputs_to_json(body.reference_inputs.iter().flat_map(|inputs| inputs.iter())),
+        "outputs": body.outputs.iter().map(output_to_json).collect::<Vec<_>>(),
+        "certificates": body
+            .certificates
+            .iter()
+            .flat_map(|certificates| certificates.iter())
+            .map(certificate_to_json)
+            .collect::<Vec<_>>(),
+        "mint": body.mint.as_ref().map(|mint| {
+            mint.iter()
+                .map(|(policy, assets)| {
+                    let assets = assets
+                        .iter()
+                        .map(|(name, quantity)| (hex::encode(&name[..]), i64::from(quantity).into()))
+                        .collect::<serde_json::Map<_, _>>();
+                    (policy.to_string(), serde_json::Value::Object(assets))
+                })
+                .collect::<serde_json::Map<_, _>>()
+        }),
+        "votes": body
+            .voting_procedures
+            .iter()
+            .flat_map(|procedures| procedures.iter())
+            .map(|(voter, votes)| {
+                serde_json::json!({
+                    "voter": voter_to_json(voter),
+                    "votes": votes.iter().map(|(proposal, procedure)| {
+                        serde_json::json!({
+                            "proposal": format!("{}#{}", proposal.transaction_id, proposal.action_index),
+                            "vote": match procedure.vote {
+                                Vote::Yes => "yes",
+                                Vote::No => "no",
+                                Vote::Abstain => "abstain",
+                            },
+                            "anchor": anchor_to_json(&procedure.anchor),
+                        })
+                    }).collect::<Vec<_>>(),
+                })
+            })
+            .collect::<Vec<_>>(),
+        "requiredSigners": body
+            .required_signers
+            .iter()
+            .flat_map(|signers| signers.iter())
+            .map(|signer| signer.to_string())
+            .collect::<Vec<_>>(),
+        "fee": body.fee,
+        "donation": body.donation.map(u64::from),
+        "collateral": inputs_to_json(body.collateral.iter().flat_map(|inputs| inputs.iter())),
+        "totalCollateral": body.total_collateral,
+        "collateralReturn": body.collateral_return.as_ref().map(output_to_json),
+        "validityStart": body.validity_interval_start,
+        "ttl": body.ttl,
+    });
+
+    Ok(Inspection {
+        summary,
+        script_integrity,
+    })
+}
+
+fn inputs_to_json<'a>(inputs: impl Iterator<Item = &'a TransactionInput>) -> Vec<String> {
+    inputs
+        .map(|input| OutputReference(input.clone()).to_string())
+        .collect()
+}
+
+fn output_to_json(output: &TransactionOutput) -> serde_json::Value {
+    match output {
+        TransactionOutput::PostAlonzo(output) => serde_json::json!({
+            "address": address_to_json(&output.address),
+            "value": value_to_json(&output.value),
+            "datum": match output.datum_option {
+                None => serde_json::Value::Null,
+                Some(DatumOption::Hash(hash)) => serde_json::json!({ "hash": hash.to_string() }),
+                Some(DatumOption::Data(ref data)) => {
+                    let mut buf = Vec::new();
+                    cbor::encode(&data.0, &mut buf).unwrap();
+                    serde_json::json!({ "inline": hex::encode(buf) })
+                }
+            },
+            "referenceScript": output.script_ref.is_some(),
+        }),
+        TransactionOutput::Legacy(output) => serde_json::json!({
+            "address": address_to_json(&output.address),
+            "value": match output.amount {
+                alonzo::Value::Coin(lovelace) => serde_json::json!({ "lovelace": lovelace }),
+                alonzo::Value::Multiasset(lovelace, ref assets) => serde_json::json!({
+                    "lovelace": lovelace,
+                    "assets": assets
+                        .iter()
+                        .map(|(policy, assets)| {
+                            let assets = assets
+                                .iter()
+                                .map(|(name, quantity)| (hex::encode(&name[..]), (*quantity).into()))
+                                .collect::<serde_json::Map<_, _>>();
+                            (policy.to_string(), serde_json::Value::Object(assets))
+                        })
+                        .collect::<serde_json::Map<_, _>>(),
+                }),
+            },
+            "datum": output.datum_hash.map(|hash| serde_json::json!({ "hash": hash.to_string() })),
+        }),
+    }
+}
+
+fn address_to_json(address: &[u8]) -> String {
+    Address::from_bytes(address)
+        .ok()
+        .and_then(|address| address.to_bech32().ok())
+        .unwrap_or_else(|| hex::encode(address))
+}
+
+fn value_to_json(value: &Value) -> serde_json::Value {
+    match value {
+        Value::Coin(lovelace) => serde_json::json!({ "lovelace": lovelace }),
+        Value::Multiasset(lovelace, assets) => serde_json::json!({
+            "lovelace": lovelace,
+            "assets": assets
+                .iter()
+                .map(|(policy, assets)| {
+                    let assets = assets
+                        .iter()
+                        .map(|(name, quantity)| (hex::encode(&name[..]), u64::from(quantity).into()))
+                        .collect::<serde_json::Map<_, _>>();
+                    (policy.to_string(), serde_json::Value::Object(assets))
+                })
+                .collect::<serde_json::Map<_, _>>(),
+        }),
+    }
+}
+
+fn credential_to_json(credential: &StakeCredential) -> serde_json::Value {
+    match credential {
+        StakeCredential::AddrKeyhash(hash) => serde_json::json!({ "key": hash.to_string() }),
+        StakeCredential::Scripthash(hash) => serde_json::json!({ "script": hash.to_string() }),
+    }
+}
+
+fn drep_to_json(drep: &DRep) -> serde_json::Value {
+    match drep {
+        DRep::Key(hash) => serde_json::json!({ "key": hash.to_string() }),
+        DRep::Script(hash) => serde_json::json!({ "drepId": drep_id(hash) }),
+        DRep::Abstain => serde_json::json!("abstain"),
+        DRep::NoConfidence => serde_json::json!("no confidence"),
+    }
+}
+
+fn voter_to_json(voter: &Voter) -> serde_json::Value {
+    let hash = |role: &str, hash: &Hash<28>| serde_json::json!({ role: hash.to_string() });
+    match voter {
+        Voter::DRepScript(script_hash) => serde_json::json!({ "drepId": drep_id(script_hash) }),
+        Voter::DRepKey(key_hash) => hash("drepKey", key_hash),
+        Voter::ConstitutionalCommitteeKey(key_hash) => hash("committeeKey", key_hash),
+        Voter::ConstitutionalCommitteeScript(script_hash) => hash("committeeScript", script_hash),
+        Voter::StakePoolKey(key_hash) => hash("stakePool", key_hash),
+    }
+}
+
+fn anchor_to_json(anchor: &Nullable<Anchor>) -> serde_json::Value {
+    match anchor {
+        Nullable::Some(anchor) => serde_json::json!({
+            "url": anchor.url,
+            "hash": anchor.content_hash.to_string(),
+        }),
+        Nullable::Null | Nullable::Undefined => serde_json::Value::Null,
+    }
+}
+
+// Certificates this tool issues are detailed; others are only named.
+fn certificate_to_json(certificate: &Certificate) -> serde_json::Value {
+    match certificate {
+        Certificate::RegDRepCert(credential, deposit, anchor) => serde_json::json!({
+            "type": "drep registration",
+            "credential": credential_to_json(credential),
+            "deposit": deposit,
+            "anchor": anchor_to_json(anchor),
+        }),
+        Certificate::UnRegDRepCert(credential, refund) => serde_json::json!({
+            "type": "drep unregistration",
+            "credential": credential_to_json(credential),
+            "refund": refund,
+        }),
+        Certificate::UpdateDRepCert(credential, anchor) => serde_json::json!({
+            "type": "drep update",
+            "credential": credential_to_json(credential),
+            "anchor": anchor_to_json(anchor),
+        }),
+        Certificate::VoteDeleg(credential, drep) => serde_json::json!({
+            "type": "vote delegation",
+            "credential": credential_to_json(credential),
+            "drep": drep_to_json(drep),
+        }),
+        Certificate::VoteRegDeleg(credential, drep, deposit) => serde_json::json!({
+            "type": "stake registration and vote delegation",
+            "credential": credential_to_json(credential),
+            "drep": drep_to_json(drep),
+            "deposit": deposit,
+        }),
+        Certificate::StakeRegistration(credential) | Certificate::Reg(credential, _) => {
+            serde_json::json!({
+                "type": "stake registration",
+                "credential": credential_to_json(credential),
+            })
+        }
+        Certificate::StakeDeregistration(credential) | Certificate::UnReg(credential, _) => {
+            serde_json::json!({
+                "type": "stake unregistration",
+                "credential": credential_to_json(credential),
+            })
+        }
+        Certificate::StakeDelegation(..) => serde_json::json!({ "type": "stake delegation" }),
+        Certificate::PoolRegistration { .. } => serde_json::json!({ "type": "pool registration" }),
+        Certificate::PoolRetirement(..) => serde_json::json!({ "type": "pool retirement" }),
+        Certificate::StakeVoteDeleg(..) => {
+            serde_json::json!({ "type": "stake and vote delegation" })
+        }
+        Certificate::StakeRegDeleg(..) => {
+            serde_json::json!({ "type": "stake registration and delegation" })
+        }
+        Certificate::StakeVoteRegDeleg(..) => {
+            serde_json::json!({ "type": "stake registration, stake and vote delegation" })
+        }
+        Certificate::AuthCommitteeHot(..) => {
+            serde_json::json!({ "type": "committee hot key authorization" })
+        }
+        Certificate::ResignCommitteeCold(..) => {
+            serde_json::json!({ "type": "committee resignation" })
+        }
+    }
+}
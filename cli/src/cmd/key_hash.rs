@@ -0,0 +1,28 @@
+//  This Source Code Form is subject to the terms of the Mozilla Public
+//  License, v. 2.0. If a copy of the MPL was not distributed with this
+//  file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use clap::Command;
+use pallas_crypto::{
+    hash::{Hash, Hasher},
+    key::ed25519::PublicKey,
+};
+
+pub(crate) fn cmd() -> Command {
+    Command::new("key-hash")
+        .about("Compute the verification key hash digest (blake2b-228) expected by --delegate and --administrator.")
+        .after_help(color_print::cstr!(
+            r#"<underline><bold>Notes:</bold></underline>
+  1. The key may be given as bech32 (e.g. addr_vk1...), as raw hex or as the cborHex of a text envelope.
+  2. Extended verification keys (e.g. addr_xvk1...) are accepted; only their public key part is hashed.
+
+<underline><bold>Example:</bold></underline>
+  <bold>key-hash</bold> \
+    <bold>--vkey</bold> $(jq -r ".cborHex" delegate.vk)
+"#              ))
+        .arg(super::arg_vkey())
+}
+
+pub(crate) fn key_hash(vkey: PublicKey) -> Hash<28> {
+    Hasher::<224>::hash(vkey.as_ref())
+}
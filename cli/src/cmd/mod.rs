@@ -2,27 +2,103 @@
 //  License, v. 2.0. If a copy of the MPL was not distributed with this
 //  file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
-use crate::pallas_extra::OutputReference;
-use clap::{Arg, ArgAction, ArgMatches, Command};
-use pallas_codec::utils::Bytes;
-use pallas_crypto::hash::{Hash, Hasher};
-use pallas_primitives::conway::{Anchor, GovActionId, Vote};
+use crate::{
+    backend::Backend,
+    cardano::{http_client, ProtocolParameters, ProtocolParametersOverrides, NETWORKS},
+    contract::{discover_contract, parse_rules, GOVERNANCE_PREFIX},
+    error::Error,
+    pallas_extra::{
+        from_validator, resolved_inputs_from_cbor, resolved_inputs_from_json, AssetQuantity,
+        BuildOptions, DatumMode, Encoding, Era, OutputReference, RedeemerOverride, Slots,
+    },
+    timings::Timings,
+    warnings::{Warning, Warnings},
+};
+use clap::{builder::PossibleValue, Arg, ArgAction, ArgMatches, Command};
+use pallas_addresses::{Address, Network};
+use pallas_codec::{
+    minicbor as cbor,
+    utils::{Bytes, PositiveCoin},
+};
+use pallas_crypto::{
+    hash::{Hash, Hasher},
+    key::ed25519::PublicKey,
+};
+use pallas_primitives::conway::{
+    Anchor, GovActionId, PlutusData, RedeemerTag, TransactionInput, Vote,
+};
+use std::{
+    fs,
+    num::NonZeroU64,
+    path::{Path, PathBuf},
+};
+use uplc::tx::ResolvedInput;
+
+mod asset_name;
+pub(crate) use asset_name::asset_name;
+
+mod completions;
+pub(crate) use completions::{completions, get_arg_shell};
 
 mod delegate;
 pub(crate) use delegate::{delegate, redelegate};
 
+mod generate_key;
+pub(crate) use generate_key::{generate_key, get_arg_key_files};
+
+mod health;
+pub(crate) use health::health;
+
+mod inspect;
+pub(crate) use inspect::{get_arg_cost_model, inspect};
+
+mod key_hash;
+pub(crate) use key_hash::key_hash;
+
+mod params;
+pub(crate) use params::params;
+
 mod revoke;
 pub(crate) use revoke::revoke;
 
+mod rules_schema;
+pub(crate) use rules_schema::rules_schema;
+
+mod sign;
+pub(crate) use sign::{get_arg_signing_keys, get_arg_tx, get_arg_tx_or_plan, sign};
+
+mod simulate_quorum;
+pub(crate) use simulate_quorum::simulate_quorum;
+
+mod submit;
+pub(crate) use submit::{get_arg_ogmios_url, submit};
+
+mod verify;
+pub(crate) use verify::{get_arg_tx_file, verify};
+
 mod vote;
 pub(crate) use vote::vote;
 
+mod voting_power;
+pub(crate) use voting_power::voting_power;
+
 #[allow(dead_code)]
 #[derive(Debug, Clone)]
 pub(crate) enum ParseFailure {
+    Address(&'static str, String),
+    Asset(&'static str, String),
     OutputReference(&'static str, String),
+    Redeemer(&'static str, String),
+    ProtocolParameters(&'static str, String),
+    ResolvedInputs(&'static str, String),
     HexString(&'static str, hex::FromHexError),
     Int(&'static str, std::num::ParseIntError),
+    LossyConversion(&'static str, String),
+    Float(&'static str, std::num::ParseFloatError),
+    Bech32(&'static str, bech32::DecodeError),
+    Duration(&'static str, String),
+    OutOfRange(&'static str, String),
+    UnexpectedLength(&'static str, usize),
 }
 
 pub(crate) fn cli() -> Command {
@@ -34,10 +110,35 @@ This command-line serves as a transaction builder various steps of the contract.
                     r#"<underline><bold>Important:</bold></underline>
   <italic>Blockfrost</italic> is used behind the scene to resolve information such as protocol parameters or UTxO.
   Therefore, you are expected to provide a valid <bold>BLOCKFROST_PROJECT_ID</bold> environment variable.
+  HTTP requests honor the usual <bold>HTTPS_PROXY</bold> and <bold>NO_PROXY</bold> environment variables.
 "#      ))
         .subcommand(vote::cmd())
         .subcommand(delegate::cmd())
         .subcommand(revoke::cmd())
+        .subcommand(params::cmd())
+        .subcommand(health::cmd())
+        .subcommand(asset_name::cmd())
+        .subcommand(rules_schema::cmd())
+        .subcommand(key_hash::cmd())
+        .subcommand(generate_key::cmd())
+        .subcommand(voting_power::cmd())
+        .subcommand(sign::cmd())
+        .subcommand(submit::cmd())
+        .subcommand(inspect::cmd())
+        .subcommand(verify::cmd())
+        .subcommand(simulate_quorum::cmd())
+        .subcommand(completions::cmd())
+}
+
+// -------------------------------------------------------- abort on warning ----
+
+const FLAG_ABORT_ON_WARNING: &str = "abort-on-warning";
+
+fn flag_abort_on_warning() -> Arg {
+    Arg::new(FLAG_ABORT_ON_WARNING)
+        .long(FLAG_ABORT_ON_WARNING)
+        .help("Fail instead of emitting the transaction when anything worth a warning came up; for unattended pipelines.")
+        .action(ArgAction::SetTrue)
 }
 
 // ----------------------------------------------------------- administrator ----
@@ -48,7 +149,7 @@ fn arg_administrator() -> Arg {
     Arg::new(ARG_ADMINISTRATOR)
         .long(ARG_ADMINISTRATOR)
         .short('a')
-        .value_name("HEX_STRING")
+        .value_name("HEX_STRING|BECH32")
         .required(true)
         .help("Verification key hash digest (blake2b-228) of an admin signatory. Use multiple times for multiple admins.")
         .action(ArgAction::Append)
@@ -57,9 +158,40 @@ fn arg_administrator() -> Arg {
 pub(crate) fn get_arg_administrators(args: &ArgMatches) -> Result<Vec<Hash<28>>, ParseFailure> {
     args.get_many::<String>(ARG_ADMINISTRATOR)
         .unwrap_or_default()
-        .map(|admin| admin.parse())
-        .collect::<Result<_, _>>()
-        .map_err(|e| ParseFailure::HexString(ARG_ADMINISTRATOR, e))
+        .map(|admin| parse_key_hash(ARG_ADMINISTRATOR, admin))
+        .collect()
+}
+
+const FLAG_ALLOW_NO_SIGNERS: &str = "allow-no-signers";
+
+fn flag_allow_no_signers() -> Arg {
+    Arg::new(FLAG_ALLOW_NO_SIGNERS)
+        .long(FLAG_ALLOW_NO_SIGNERS)
+        .help("Build the transaction even when no --administrator is given, and thus without any required signer.")
+        .action(ArgAction::SetTrue)
+}
+
+pub(crate) fn get_allow_no_signers(args: &ArgMatches) -> bool {
+    args.get_flag(FLAG_ALLOW_NO_SIGNERS)
+}
+
+// NOTE: Without any required signer, nothing binds the transaction to its administrators; so
+// whoever gets hold of it may submit it. This is only ever acceptable when asked for.
+pub(crate) fn ensure_signers(
+    administrators: &[Hash<28>],
+    allow_no_signers: bool,
+    warnings: &Warnings,
+) -> Result<(), Error> {
+    if !administrators.is_empty() {
+        return Ok(());
+    }
+
+    if allow_no_signers {
+        warnings.push(Warning::NoSigners);
+        Ok(())
+    } else {
+        Err(Error::NoSigners)
+    }
 }
 
 // ----------------------------------------------------------------- anchor ----
@@ -71,28 +203,322 @@ fn arg_anchor() -> Arg {
         .long(ARG_ANCHOR)
         .short('a')
         .value_name("URL")
-        .help("An (optional) URL to an anchor file containing rationale for the vote.")
-        .action(ArgAction::Set)
-}
-
-pub(crate) async fn get_arg_anchor(args: &ArgMatches) -> Option<Anchor> {
-    if let Some(url) = args.get_one::<String>(ARG_ANCHOR) {
-        let response = reqwest::get(url)
-            .await
-            .expect("failed to fetch anchor at URL: {url}");
-        match response.status() {
-            status if status.is_success() => {
-                let content_hash = Hasher::<256>::hash(response.bytes().await.unwrap().as_ref());
-                Some(Anchor {
-                    url: url.to_string(),
-                    content_hash,
-                })
+        .help("An (optional) URL to an anchor file containing rationale.")
+        .action(ArgAction::Set)
+}
+
+const ARG_ANCHOR_HASH: &str = "anchor-hash";
+
+fn arg_anchor_hash() -> Arg {
+    Arg::new(ARG_ANCHOR_HASH)
+        .long(ARG_ANCHOR_HASH)
+        .value_name("HEX_STRING")
+        .requires(ARG_ANCHOR)
+        .conflicts_with(ARG_ANCHOR_FILE)
+        .help(
+            "Content hash digest (blake2b-256) of the anchor. When set, the anchor isn't fetched.",
+        )
+        .action(ArgAction::Set)
+}
+
+const ARG_ANCHOR_FILE: &str = "anchor-file";
+
+fn arg_anchor_file() -> Arg {
+    Arg::new(ARG_ANCHOR_FILE)
+        .long(ARG_ANCHOR_FILE)
+        .value_name("FILEPATH")
+        .requires(ARG_ANCHOR)
+        .help("A local copy of the anchor's content, hashed in place of fetching the anchor URL.")
+        .action(ArgAction::Set)
+}
+
+pub(crate) async fn get_arg_anchor(
+    args: &ArgMatches,
+    warnings: &Warnings,
+) -> Result<Option<Anchor>, Error> {
+    let hash = args
+        .get_one::<String>(ARG_ANCHOR_HASH)
+        .map(|s| s.parse())
+        .transpose()
+        .map_err(|e| ParseFailure::HexString(ARG_ANCHOR_HASH, e))?;
+
+    resolve_anchor(
+        &http_client(args.get_flag(FLAG_INSECURE)),
+        args.get_one::<String>(ARG_ANCHOR).map(String::as_str),
+        hash,
+        args.get_one::<String>(ARG_ANCHOR_FILE).map(Path::new),
+        warnings,
+    )
+    .await
+}
+
+// Votes may carry one anchor per proposal, paired positionally. Pinning the content (through
+// --anchor-hash or --anchor-file) is then only possible with a single anchor, as it'd otherwise
+// be unclear which anchor it pins.
+pub(crate) async fn get_arg_anchors(
+    args: &ArgMatches,
+    warnings: &Warnings,
+) -> Result<Vec<Anchor>, Error> {
+    let urls = args
+        .get_many::<String>(ARG_ANCHOR)
+        .unwrap_or_default()
+        .collect::<Vec<_>>();
+
+    if urls.len() <= 1 {
+        return Ok(get_arg_anchor(args, warnings).await?.into_iter().collect());
+    }
+
+    if args.contains_id(ARG_ANCHOR_HASH) || args.contains_id(ARG_ANCHOR_FILE) {
+        return Err(Error::AmbiguousAnchorContent {
+            anchors: urls.len(),
+        });
+    }
+
+    let client = http_client(args.get_flag(FLAG_INSECURE));
+    let mut anchors = vec![];
+    for url in urls {
+        if let Some(anchor) = resolve_anchor(&client, Some(url), None, None, warnings).await? {
+            anchors.push(anchor);
+        }
+    }
+
+    Ok(anchors)
+}
+
+// Anchors are optional everywhere (votes, DRep registrations, ...), and their content hash can
+// either be given upfront, computed from a local file or computed from the content served at the
+// anchor's URL; in this order of preference.
+pub(crate) async fn resolve_anchor(
+    client: &reqwest::Client,
+    url: Option<&str>,
+    hash: Option<Hash<32>>,
+    file: Option<&Path>,
+    warnings: &Warnings,
+) -> Result<Option<Anchor>, Error> {
+    let Some(url) = url else {
+        return if hash.is_some() || file.is_some() {
+            Err(Error::MissingAnchorUrl)
+        } else {
+            Ok(None)
+        };
+    };
+
+    let content_hash = match (hash, file) {
+        (Some(hash), _) => hash,
+        (None, Some(file)) => {
+            let content =
+                fs::read(file).map_err(|e| Error::FailedToReadFile(file.to_path_buf(), e))?;
+            validate_anchor_content(url, &content, warnings)?;
+            Hasher::<256>::hash(&content)
+        }
+        (None, None) => {
+            let response = client
+                .get(url)
+                .send()
+                .await
+                .map_err(|e| Error::FailedToFetchAnchor(url.to_string(), e.to_string()))?;
+            match response.status() {
+                status if status.is_success() => {
+                    let content_type = response
+                        .headers()
+                        .get(reqwest::header::CONTENT_TYPE)
+                        .and_then(|value| value.to_str().ok())
+                        .unwrap_or_default()
+                        .to_string();
+                    if !content_type.contains("json") {
+                        warnings.push(Warning::AnchorUnexpectedContentType {
+                            url: url.to_string(),
+                            content_type,
+                        });
+                    }
+                    let content = response
+                        .bytes()
+                        .await
+                        .map_err(|e| Error::FailedToFetchAnchor(url.to_string(), e.to_string()))?;
+                    validate_anchor_content(url, &content, warnings)?;
+                    Hasher::<256>::hash(content.as_ref())
+                }
+                status => {
+                    return Err(Error::FailedToFetchAnchor(
+                        url.to_string(),
+                        format!("server said: {status:?}"),
+                    ))
+                }
             }
-            status => panic!("failed to fetch anchor content, server said: {status:?}"),
         }
-    } else {
-        None
+    };
+
+    Ok(Some(Anchor {
+        url: url.to_string(),
+        content_hash,
+    }))
+}
+
+// ------------------------------------------------------------------ asset ----
+
+const ARG_ASSET: &str = "asset";
+
+fn arg_asset() -> Arg {
+    Arg::new(ARG_ASSET)
+        .long(ARG_ASSET)
+        .value_name("POLICY_ID.ASSET_NAME:QUANTITY")
+        .help("A native asset, taken from the fuel, for the contract to hold alongside its state token. Use multiple times for multiple assets.")
+        .action(ArgAction::Append)
+}
+
+pub(crate) fn get_arg_assets(args: &ArgMatches) -> Result<Vec<AssetQuantity>, ParseFailure> {
+    args.get_many::<String>(ARG_ASSET)
+        .unwrap_or_default()
+        .map(|asset| asset.parse().map_err(|e| ParseFailure::Asset(ARG_ASSET, e)))
+        .collect()
+}
+
+// ------------------------------------------------------ assume convergence ----
+
+const ARG_ASSUME_YES_CONVERGENCE: &str = "assume-yes-convergence";
+
+fn arg_assume_yes_convergence() -> Arg {
+    Arg::new(ARG_ASSUME_YES_CONVERGENCE)
+        .long(ARG_ASSUME_YES_CONVERGENCE)
+        .value_name("FILEPATH")
+        .num_args(0..=1)
+        .default_missing_value(".zhuli-cache.json")
+        .help("Remember converged fees and execution units in a cache file, and re-use them as-is for identical builds.")
+        .action(ArgAction::Set)
+}
+
+// ------------------------------------------------------------ best effort ----
+
+const FLAG_BEST_EFFORT: &str = "best-effort";
+
+fn flag_best_effort() -> Arg {
+    Arg::new(FLAG_BEST_EFFORT)
+        .long(FLAG_BEST_EFFORT)
+        .help("Emit a transaction, possibly over-paying fees, even when fees and execution units haven't converged.")
+        .action(ArgAction::SetTrue)
+}
+
+const FLAG_INSECURE: &str = "insecure";
+
+fn flag_insecure() -> Arg {
+    Arg::new(FLAG_INSECURE)
+        .long(FLAG_INSECURE)
+        .requires(ARG_ANCHOR)
+        .help("Do not verify TLS certificates when fetching the anchor (e.g. self-signed hosts). Use with caution.")
+        .action(ArgAction::SetTrue)
+}
+
+// Governance metadata (CIP-0100 and derivatives) are JSON-LD documents. Hashing anything else
+// (e.g. an HTML error page served with a 200) would record a meaningless anchor.
+fn validate_anchor_content(url: &str, content: &[u8], warnings: &Warnings) -> Result<(), Error> {
+    let document: serde_json::Value = serde_json::from_slice(content)
+        .map_err(|e| Error::InvalidAnchorContent(url.to_string(), e.to_string()))?;
+
+    if document.get("@context").is_none() {
+        warnings.push(Warning::AnchorNotJsonLd {
+            url: url.to_string(),
+        });
+    }
+
+    Ok(())
+}
+
+// ----------------------------------------------------------------- bundle ----
+
+const ARG_BUNDLE: &str = "bundle";
+
+fn arg_bundle() -> Arg {
+    Arg::new(ARG_BUNDLE)
+        .long(ARG_BUNDLE)
+        .value_name("FILEPATH")
+        .help("Also write the transaction, together with every input it resolves, to this file; for signers needing the full context (e.g. hardware wallets).")
+        .action(ArgAction::Set)
+}
+
+const ARG_PLAN: &str = "plan";
+
+fn arg_plan() -> Arg {
+    Arg::new(ARG_PLAN)
+        .long(ARG_PLAN)
+        .value_name("FILEPATH")
+        .help("Also write a review plan to this file: the transaction, its fee breakdown, resolved inputs, rules and warnings.")
+        .action(ArgAction::Set)
+}
+
+// --------------------------------------------------------- change address ----
+
+const ARG_CHANGE_ADDRESS: &str = "change-address";
+
+fn arg_change_address() -> Arg {
+    Arg::new(ARG_CHANGE_ADDRESS)
+        .long(ARG_CHANGE_ADDRESS)
+        .value_name("BECH32")
+        .help("Send the change to this address rather than back to the fuel's address. The collateral return still goes to the fuel's address.")
+        .action(ArgAction::Set)
+}
+
+fn get_arg_change_address(args: &ArgMatches) -> Result<Option<Address>, ParseFailure> {
+    args.get_one::<String>(ARG_CHANGE_ADDRESS)
+        .map(|s| {
+            Address::from_bech32(s)
+                .map_err(|e| ParseFailure::Address(ARG_CHANGE_ADDRESS, e.to_string()))
+        })
+        .transpose()
+}
+
+// Where the change of a transaction goes; defaults to where the fuel came from.
+fn change_address(
+    network: &impl Backend,
+    options: &BuildOptions,
+    fuel_address: &Bytes,
+) -> Result<Bytes, Error> {
+    match options.change_address {
+        Some(ref address) => {
+            ensure_network(network, address)?;
+            Ok(address.to_vec().into())
+        }
+        None => Ok(fuel_address.clone()),
+    }
+}
+
+fn ensure_network(network: &impl Backend, address: &Address) -> Result<(), Error> {
+    if address.network() != Some(network.network_id()) {
+        return Err(Error::UnexpectedNetwork {
+            address: address.to_bech32().unwrap_or_else(|_| address.to_hex()),
+            expected: network.network_id(),
+        });
     }
+    Ok(())
+}
+
+// ------------------------------------------------------------- collateral ----
+
+const FLAG_COLLATERAL_ONLY_ADA: &str = "collateral-only-ada";
+
+fn flag_collateral_only_ada() -> Arg {
+    Arg::new(FLAG_COLLATERAL_ONLY_ADA)
+        .long(FLAG_COLLATERAL_ONLY_ADA)
+        .help("Refuse to use a fuel holding native tokens as collateral, for tools expecting pure-ada collateral.")
+        .action(ArgAction::SetTrue)
+}
+
+const FLAG_NO_COLLATERAL_RETURN: &str = "no-collateral-return";
+
+fn flag_no_collateral_return() -> Arg {
+    Arg::new(FLAG_NO_COLLATERAL_RETURN)
+        .long(FLAG_NO_COLLATERAL_RETURN)
+        .help("Omit the collateral return, forfeiting the whole (pure-ada) collateral should scripts fail. Meant for small collateral that cannot afford a return output.")
+        .action(ArgAction::SetTrue)
+}
+
+const ARG_COLLATERAL_PERCENT: &str = "collateral-percent";
+
+fn arg_collateral_percent() -> Arg {
+    Arg::new(ARG_COLLATERAL_PERCENT)
+        .long(ARG_COLLATERAL_PERCENT)
+        .value_name("PERCENT")
+        .help("Override the protocol's collateral percentage (e.g. 150), for providers reporting it in another unit.")
+        .action(ArgAction::Set)
 }
 
 // --------------------------------------------------------------- contract ----
@@ -116,6 +542,62 @@ pub(crate) fn get_arg_contract(args: &ArgMatches) -> Result<Option<OutputReferen
         .map_err(|e| ParseFailure::OutputReference(ARG_CONTRACT, e))
 }
 
+// When omitted, the contract is discovered from the validator instead. Commands offering this
+// must therefore make --contract, --validator and --validator-hash a required group.
+pub(crate) async fn get_arg_contract_or_discover(
+    args: &ArgMatches,
+    network: &impl Backend,
+    prefix: &[u8],
+) -> Result<OutputReference, Error> {
+    match get_arg_contract(args)? {
+        Some(contract) => Ok(contract),
+        None => discover_contract(network, get_arg_validator_hash(args)?, prefix).await,
+    }
+}
+
+pub(crate) fn get_arg_contracts(args: &ArgMatches) -> Result<Vec<OutputReference>, ParseFailure> {
+    args.get_many::<String>(ARG_CONTRACT)
+        .unwrap_or_default()
+        .map(|s| s.parse())
+        .collect::<Result<_, _>>()
+        .map_err(|e| ParseFailure::OutputReference(ARG_CONTRACT, e))
+}
+
+// ------------------------------------------------------------------ datum ----
+
+const ARG_DATUM: &str = "datum";
+
+fn arg_datum() -> Arg {
+    Arg::new(ARG_DATUM)
+        .long(ARG_DATUM)
+        .value_name("MODE")
+        .value_parser(["none", "inline", "hash"])
+        .default_value("none")
+        .help("Whether the contract output carries its rules as datum: not at all, inline, or by hash (with the datum in the witness set).")
+        .action(ArgAction::Set)
+}
+
+pub(crate) fn get_arg_datum(args: &ArgMatches) -> DatumMode {
+    match args.get_one::<String>(ARG_DATUM).map(|s| s.as_str()) {
+        Some("none") | None => DatumMode::None,
+        Some("inline") => DatumMode::Inline,
+        Some("hash") => DatumMode::Hash,
+        Some(mode) => unreachable!("unexpected datum mode: {mode}"),
+    }
+}
+
+// --------------------------------------------------------- debug attempts ----
+
+const ARG_DEBUG_ATTEMPTS: &str = "debug-attempts";
+
+fn arg_debug_attempts() -> Arg {
+    Arg::new(ARG_DEBUG_ATTEMPTS)
+        .long(ARG_DEBUG_ATTEMPTS)
+        .value_name("DIRPATH")
+        .help("Write each intermediate transaction (attempt-N.cbor), with its fee and execution units (attempt-N.json), to this directory.")
+        .action(ArgAction::Set)
+}
+
 // --------------------------------------------------------------- delegate ----
 
 const ARG_DELEGATE: &str = "delegate";
@@ -124,7 +606,7 @@ fn arg_delegate() -> Arg {
     Arg::new(ARG_DELEGATE)
         .long(ARG_DELEGATE)
         .short('d')
-        .value_name("HEX_STRING")
+        .value_name("HEX_STRING|BECH32")
         .help("Verification key hash digest (blake2b-228) of a delegate signatory. Use multiple times for multiple delegates.")
         .action(ArgAction::Append)
 }
@@ -132,131 +614,1244 @@ fn arg_delegate() -> Arg {
 pub(crate) fn get_arg_delegates(args: &ArgMatches) -> Result<Vec<Hash<28>>, ParseFailure> {
     args.get_many::<String>(ARG_DELEGATE)
         .unwrap_or_default()
-        .map(|delegate| delegate.parse())
-        .collect::<Result<_, _>>()
-        .map_err(|e| ParseFailure::HexString(ARG_DELEGATE, e))
+        .map(|delegate| parse_key_hash(ARG_DELEGATE, delegate))
+        .collect()
 }
 
-// ------------------------------------------------------------------- fuel ----
+// --------------------------------------------------------------- donation ----
 
-const ARG_FUEL: &str = "fuel";
+const ARG_DONATION: &str = "donation";
 
-fn arg_fuel() -> Arg {
-    Arg::new(ARG_FUEL)
-        .long(ARG_FUEL)
-        .short('f')
-        .required(true)
-        .value_name("TX_ID#IX")
-        .help("A UTxO to use as fuel for the transaction. Must be suitable for collateral use.")
+fn arg_donation() -> Arg {
+    Arg::new(ARG_DONATION)
+        .long(ARG_DONATION)
+        .value_name("LOVELACE")
+        .help("Donate this amount to the treasury, taken out of the change.")
         .action(ArgAction::Set)
 }
 
-pub(crate) fn get_arg_fuel(args: &ArgMatches) -> Result<OutputReference, ParseFailure> {
-    args.get_one::<String>(ARG_FUEL)
-        .unwrap()
-        .parse()
-        .map_err(|e| ParseFailure::OutputReference(ARG_FUEL, e))
+// NOTE: Only vote and delegate can donate.
+fn get_arg_donation(args: &ArgMatches) -> Result<Option<PositiveCoin>, ParseFailure> {
+    match args.try_get_one::<String>(ARG_DONATION) {
+        Ok(Some(s)) => s
+            .parse::<NonZeroU64>()
+            .map(|lovelace| Some(PositiveCoin::try_from(lovelace.get()).unwrap()))
+            .map_err(|e| ParseFailure::Int(ARG_DONATION, e)),
+        _ => Ok(None),
+    }
 }
 
-// --------------------------------------------------------------- proposal ----
+// ------------------------------------------------------ double check eval ----
 
-const ARG_PROPOSAL: &str = "proposal";
+const FLAG_DOUBLE_CHECK_EVAL: &str = "double-check-eval";
 
-fn arg_proposal() -> Arg {
-    Arg::new(ARG_PROPOSAL)
-        .long(ARG_PROPOSAL)
-        .short('p')
-        .required(true)
-        .value_name("TX_ID#IX")
-        .help("The proposal procedure identifier that's being voted on.")
+fn flag_double_check_eval() -> Arg {
+    Arg::new(FLAG_DOUBLE_CHECK_EVAL)
+        .long(FLAG_DOUBLE_CHECK_EVAL)
+        .help("Evaluate scripts once more on the final transaction, and fail if their execution units no longer fit.")
+        .action(ArgAction::SetTrue)
+}
+
+// --------------------------------------------------------------- encoding ----
+
+const ARG_ENCODING: &str = "encoding";
+
+fn arg_encoding() -> Arg {
+    Arg::new(ARG_ENCODING)
+        .long(ARG_ENCODING)
+        .visible_alias("output-format")
+        .value_name("FORMAT")
+        .value_parser([
+            PossibleValue::new("text-envelope").alias("hex"),
+            PossibleValue::new("cbor-hex"),
+            PossibleValue::new("cbor-bin").alias("raw"),
+            PossibleValue::new("base64"),
+            PossibleValue::new("witness-request"),
+        ])
+        .default_value("text-envelope")
+        .help("How to emit the transaction: a hex text-envelope, the bare hex-encoded CBOR on one line, the raw CBOR bytes, the base64-encoded CBOR, or a witness request listing only the body hash and every key that must sign (required signers and owners of key-locked inputs).")
         .action(ArgAction::Set)
 }
 
-pub(crate) fn get_arg_proposal(args: &ArgMatches) -> Result<GovActionId, ParseFailure> {
-    let OutputReference(utxo_like) = args
-        .get_one::<String>(ARG_PROPOSAL)
-        .unwrap()
-        .parse()
-        .map_err(|e| ParseFailure::OutputReference(ARG_PROPOSAL, e))?;
+fn get_arg_encoding(args: &ArgMatches) -> Encoding {
+    match args.get_one::<String>(ARG_ENCODING).map(|s| s.as_str()) {
+        Some("text-envelope" | "hex") | None => Encoding::TextEnvelope,
+        Some("cbor-hex") => Encoding::CborHex,
+        Some("cbor-bin" | "raw") => Encoding::Raw,
+        Some("base64") => Encoding::Base64,
+        Some("witness-request") => Encoding::WitnessRequest,
+        Some(encoding) => unreachable!("unexpected encoding: {encoding}"),
+    }
+}
 
-    Ok(GovActionId {
-        transaction_id: utxo_like.transaction_id,
-        action_index: utxo_like.index as u32,
-    })
+const ARG_OUT: &str = "out";
+
+fn arg_out() -> Arg {
+    Arg::new(ARG_OUT)
+        .long(ARG_OUT)
+        .visible_alias("out-file")
+        .value_name("FILEPATH")
+        .help("Write the transaction to this file rather than to stdout, creating parent directories as needed.")
+        .action(ArgAction::Set)
 }
 
-// ----------------------------------------------------------------- quorum ----
+pub(crate) fn get_arg_out(args: &ArgMatches) -> Option<PathBuf> {
+    args.get_one::<String>(ARG_OUT).map(PathBuf::from)
+}
 
-const ARG_QUORUM: &str = "quorum";
+// ------------------------------------------------------------------ epoch ----
 
-fn arg_quorum() -> Arg {
-    Arg::new(ARG_QUORUM)
-        .long(ARG_QUORUM)
-        .short('q')
+const ARG_EPOCH: &str = "epoch";
+
+fn arg_epoch() -> Arg {
+    Arg::new(ARG_EPOCH)
+        .long(ARG_EPOCH)
         .value_name("UINT")
-        .help("Minimum number of delegates to authorize votes. Default to the total number of delegates (plenum).")
+        .help("Use the protocol parameters of this (past) epoch (cost model included) instead of the current ones; e.g. to reproduce a historical transaction.")
         .action(ArgAction::Set)
 }
 
-pub(crate) fn get_arg_quorum(args: &ArgMatches) -> Result<Option<usize>, ParseFailure> {
-    args.get_one::<String>(ARG_QUORUM)
-        .map(|s| s.parse().map_err(|e| ParseFailure::Int(ARG_QUORUM, e)))
+pub(crate) fn get_arg_epoch(args: &ArgMatches) -> Result<Option<u64>, ParseFailure> {
+    args.get_one::<String>(ARG_EPOCH)
+        .map(|s| s.parse().map_err(|e| ParseFailure::Int(ARG_EPOCH, e)))
         .transpose()
 }
 
-// -------------------------------------------------------------- validator ----
+// -------------------------------------------------------------------- era ----
 
-const ARG_VALIDATOR: &str = "validator";
+const ARG_ERA: &str = "era";
 
-fn arg_validator() -> Arg {
-    Arg::new(ARG_VALIDATOR)
-        .long(ARG_VALIDATOR)
-        .short('v')
-        .value_name("HEX_STRING")
-        .help("The compiled validator code, hex-encoded. (e.g jq -r '.validators[0].compiledCode' plutus.json)")
+fn arg_era() -> Arg {
+    Arg::new(ARG_ERA)
+        .long(ARG_ERA)
+        .value_name("ERA")
+        .value_parser(["conway"])
+        .default_value("conway")
+        .help("The ledger era to build the transaction for.")
         .action(ArgAction::Set)
 }
 
-pub(crate) fn get_arg_validator(args: &ArgMatches) -> Result<Option<Bytes>, ParseFailure> {
-    args.get_one::<String>(ARG_VALIDATOR)
+fn get_arg_era(args: &ArgMatches) -> Era {
+    match args.get_one::<String>(ARG_ERA).map(|s| s.as_str()) {
+        Some("conway") | None => Era::Conway,
+        Some(era) => unreachable!("unexpected era: {era}"),
+    }
+}
+
+// -------------------------------------------------------- expected signers ----
+
+const ARG_EXPECTED_SIGNERS: &str = "expected-signers";
+
+fn arg_expected_signers() -> Arg {
+    Arg::new(ARG_EXPECTED_SIGNERS)
+        .long(ARG_EXPECTED_SIGNERS)
+        .value_name("UINT")
+        .help("How many signatures the transaction will carry, all included (required signers and fuel owners alike). Overrides the fee estimate's guess, which assumes one signature per input and per required signer.")
+        .action(ArgAction::Set)
+}
+
+fn get_arg_expected_signers(args: &ArgMatches) -> Result<Option<usize>, ParseFailure> {
+    args.get_one::<String>(ARG_EXPECTED_SIGNERS)
         .map(|s| {
-            hex::decode(s)
-                .map(Bytes::from)
-                .map_err(|e| ParseFailure::HexString(ARG_VALIDATOR, e))
+            s.parse()
+                .map_err(|e| ParseFailure::Int(ARG_EXPECTED_SIGNERS, e))
         })
         .transpose()
 }
 
-// ------------------------------------------------------------------- vote ----
+// ---------------------------------------------------------------- explain ----
+
+const FLAG_EXPLAIN: &str = "explain";
+
+fn flag_explain() -> Arg {
+    Arg::new(FLAG_EXPLAIN)
+        .long(FLAG_EXPLAIN)
+        .help("Report, as JSON on stderr, who authorizes the transaction and against which rules.")
+        .action(ArgAction::SetTrue)
+}
 
-pub(crate) fn get_arg_vote(args: &ArgMatches) -> Vote {
-    match args.get_one::<clap::Id>("vote").unwrap().as_str() {
-        "yes" => Vote::Yes,
-        "no" => Vote::No,
-        "abstain" => Vote::Abstain,
-        _ => unreachable!(),
+// Audit trail of the authorization path taken by a command, kept apart from the transaction on
+// stdout.
+fn explain(options: &BuildOptions, explanation: impl FnOnce() -> serde_json::Value) {
+    if options.explain || options.plan.is_some() {
+        let explanation = explanation();
+        if options.explain {
+            eprintln!("{}", serde_json::to_string_pretty(&explanation).unwrap());
+        }
+        options.explanation.replace(Some(explanation));
     }
 }
 
-fn flag_yes() -> Arg {
-    Arg::new("yes")
-        .short('y')
-        .long("yes")
-        .help("Approve the governance proposal")
-        .action(ArgAction::SetTrue)
+// Delegates and quorum of some rules, as well as whether the given signatories satisfy them.
+fn explain_rules(rules: &PlutusData, signatories: &[Hash<28>]) -> serde_json::Value {
+    match parse_rules(rules) {
+        Some(rules) => serde_json::json!({
+            "delegates": rules.delegates.iter().map(|h| h.to_string()).collect::<Vec<_>>(),
+            "quorum": rules.quorum,
+            "satisfied": rules.is_satisfied_by(signatories),
+        }),
+        None => serde_json::json!({
+            "delegates": null,
+            "quorum": null,
+            "satisfied": null,
+        }),
+    }
 }
 
-fn flag_no() -> Arg {
-    Arg::new("no")
-        .short('n')
-        .long("no")
-        .help("Reject the governance proposal")
+// -------------------------------------------------------------------- fee ----
+
+const FLAG_DUMP_FEE_MODEL: &str = "dump-fee-model";
+
+fn flag_dump_fee_model() -> Arg {
+    Arg::new(FLAG_DUMP_FEE_MODEL)
+        .long(FLAG_DUMP_FEE_MODEL)
+        .help("Report on stderr how the fee was computed, term by term, once fees and execution units have converged.")
         .action(ArgAction::SetTrue)
 }
 
-fn flag_abstain() -> Arg {
-    Arg::new("abstain")
-        .long("abstain")
-        .help("Abstain from the governance proposal voting")
-        .action(ArgAction::SetTrue)
+const ARG_FEE_CONSTANT: &str = "fee-constant";
+
+fn arg_fee_constant() -> Arg {
+    Arg::new(ARG_FEE_CONSTANT)
+        .long(ARG_FEE_CONSTANT)
+        .value_name("LOVELACE")
+        .help("Override the protocol's constant fee (a.k.a min_fee_b).")
+        .action(ArgAction::Set)
+}
+
+const ARG_FEE_COEFFICIENT: &str = "fee-coefficient";
+
+fn arg_fee_coefficient() -> Arg {
+    Arg::new(ARG_FEE_COEFFICIENT)
+        .long(ARG_FEE_COEFFICIENT)
+        .value_name("LOVELACE")
+        .help("Override the protocol's per-byte fee (a.k.a min_fee_a).")
+        .action(ArgAction::Set)
+}
+
+const ARG_MAX_FEE: &str = "max-fee";
+
+fn arg_max_fee() -> Arg {
+    Arg::new(ARG_MAX_FEE)
+        .long(ARG_MAX_FEE)
+        .value_name("LOVELACE")
+        .help("Fail rather than emit a transaction whose fee exceeds this amount; a safety net against absurd parameters.")
+        .action(ArgAction::Set)
+}
+
+fn get_arg_max_fee(args: &ArgMatches) -> Result<Option<u64>, ParseFailure> {
+    args.get_one::<String>(ARG_MAX_FEE)
+        .map(|s| s.parse().map_err(|e| ParseFailure::Int(ARG_MAX_FEE, e)))
+        .transpose()
+}
+
+pub(crate) fn get_protocol_parameters_overrides(
+    args: &ArgMatches,
+) -> Result<ProtocolParametersOverrides, ParseFailure> {
+    let get_u64 = |arg: &'static str| {
+        args.get_one::<String>(arg)
+            .map(|s| s.parse().map_err(|e| ParseFailure::Int(arg, e)))
+            .transpose()
+    };
+
+    let get_f64 = |arg: &'static str| {
+        args.get_one::<String>(arg)
+            .map(|s| s.parse().map_err(|e| ParseFailure::Float(arg, e)))
+            .transpose()
+    };
+
+    Ok(ProtocolParametersOverrides {
+        collateral_percent: get_u64(ARG_COLLATERAL_PERCENT)?,
+        fee_constant: get_u64(ARG_FEE_CONSTANT)?,
+        fee_coefficient: get_u64(ARG_FEE_COEFFICIENT)?,
+        price_mem: get_f64(ARG_PRICE_MEM)?,
+        price_steps: get_f64(ARG_PRICE_STEPS)?,
+        fee_bump: 0,
+    })
+}
+
+// --------------------------------------------------------------- key hash ----
+
+// Key hashes are either hex-encoded, or bech32-encoded as per CIP-0005 (e.g. addr_vkh, stake_vkh)
+// or CIP-0129 (e.g. drep, cc_hot) in which case they're prefixed with a one-byte header.
+fn parse_key_hash(arg: &'static str, str: &str) -> Result<Hash<28>, ParseFailure> {
+    let bytes = if str.chars().all(|c| c.is_ascii_hexdigit()) {
+        hex::decode(str).map_err(|e| ParseFailure::HexString(arg, e))?
+    } else {
+        bech32::decode(str)
+            .map_err(|e| ParseFailure::Bech32(arg, e))?
+            .1
+    };
+
+    // NOTE: A CIP-0129 header's lower nibble is 0x02 for key hashes, and 0x03 for script hashes.
+    // Only the former can be signatories.
+    let key_hash = match &bytes[..] {
+        [header, key_hash @ ..] if key_hash.len() == 28 && header & 0x0F == 0x02 => key_hash,
+        key_hash => key_hash,
+    };
+
+    if key_hash.len() == 28 {
+        Ok(Hash::from(key_hash))
+    } else {
+        Err(ParseFailure::UnexpectedLength(arg, key_hash.len()))
+    }
+}
+
+// ------------------------------------------------------------------ force ----
+
+const FLAG_FORCE: &str = "force";
+
+fn flag_force() -> Arg {
+    Arg::new(FLAG_FORCE)
+        .long(FLAG_FORCE)
+        .help("Vote even when the DRep has already cast the very same vote on the proposal.")
+        .action(ArgAction::SetTrue)
+}
+
+pub(crate) fn get_force(args: &ArgMatches) -> bool {
+    args.get_flag(FLAG_FORCE)
+}
+
+const FLAG_REPLACE_VOTE: &str = "replace-vote";
+
+fn flag_replace_vote() -> Arg {
+    Arg::new(FLAG_REPLACE_VOTE)
+        .long(FLAG_REPLACE_VOTE)
+        .help("Confirm that the vote is meant to overwrite a different vote the DRep already cast on the proposal.")
+        .action(ArgAction::SetTrue)
+}
+
+pub(crate) fn get_replace_vote(args: &ArgMatches) -> bool {
+    args.get_flag(FLAG_REPLACE_VOTE)
+}
+
+// ------------------------------------------------------------------- fuel ----
+
+const ARG_FUEL: &str = "fuel";
+
+fn arg_fuel() -> Arg {
+    Arg::new(ARG_FUEL)
+        .long(ARG_FUEL)
+        .short('f')
+        .required(true)
+        .value_name("TX_ID#IX")
+        .help("A UTxO to use as fuel for the transaction. Use multiple times to let the transaction pick from several UTxOs; the first one is always spent, and must be suitable for collateral use.")
+        .action(ArgAction::Append)
+}
+
+pub(crate) fn get_arg_fuels(args: &ArgMatches) -> Result<Vec<OutputReference>, ParseFailure> {
+    let mut fuels: Vec<OutputReference> = vec![];
+    for fuel in args.get_many::<String>(ARG_FUEL).unwrap_or_default() {
+        let OutputReference(fuel) = fuel
+            .parse()
+            .map_err(|e| ParseFailure::OutputReference(ARG_FUEL, e))?;
+        if !fuels.iter().any(|OutputReference(known)| known == &fuel) {
+            fuels.push(OutputReference(fuel));
+        }
+    }
+    Ok(fuels)
+}
+
+// NOTE: Another process (e.g. a second bot instance) may consume the fuel between two runs. We
+// fail early and distinctly so that automation can pick another fuel and retry.
+async fn ensure_unspent_fuel(network: &impl Backend, fuel: &TransactionInput) -> Result<(), Error> {
+    if network.is_unspent(fuel).await? {
+        Ok(())
+    } else {
+        Err(Error::FuelAlreadySpent {
+            input: OutputReference(fuel.clone()).to_string(),
+        })
+    }
+}
+
+// ------------------------------------------------------------ keep change ----
+
+const ARG_KEEP_CHANGE_ABOVE: &str = "keep-change-above";
+
+fn arg_keep_change_above() -> Arg {
+    Arg::new(ARG_KEEP_CHANGE_ABOVE)
+        .long(ARG_KEEP_CHANGE_ABOVE)
+        .value_name("LOVELACE")
+        .help("Give away (as extra fee) any lovelace-only change below this amount, instead of creating a dust output. Change below the minimum UTxO value is always given away.")
+        .action(ArgAction::Set)
+}
+
+// ----------------------------------------------------- max build attempts ----
+
+const ARG_MAX_BUILD_ATTEMPTS: &str = "max-build-attempts";
+
+fn arg_max_build_attempts() -> Arg {
+    Arg::new(ARG_MAX_BUILD_ATTEMPTS)
+        .long(ARG_MAX_BUILD_ATTEMPTS)
+        .value_name("UINT")
+        .default_value("3")
+        .help("How many times to rebuild the transaction, adjusting fee and execution units, before giving up on convergence.")
+        .action(ArgAction::Set)
+}
+
+fn get_arg_max_build_attempts(args: &ArgMatches) -> Result<u8, ParseFailure> {
+    args.get_one::<String>(ARG_MAX_BUILD_ATTEMPTS)
+        .unwrap()
+        .parse()
+        .map_err(|e| ParseFailure::Int(ARG_MAX_BUILD_ATTEMPTS, e))
+}
+
+// ---------------------------------------------------------------- network ----
+
+const ARG_NETWORK: &str = "network";
+
+fn arg_network() -> Arg {
+    Arg::new(ARG_NETWORK)
+        .long(ARG_NETWORK)
+        .value_name("NETWORK")
+        .value_parser(NETWORKS)
+        .help("The network to build for; otherwise inferred from the BLOCKFROST_PROJECT_ID. Together with --protocol-params and --resolved-inputs, no credentials are needed; though only a first delegation can then be built, as other commands must look up the contract.")
+        .action(ArgAction::Set)
+}
+
+pub(crate) fn get_arg_network(args: &ArgMatches) -> Option<&str> {
+    args.get_one::<String>(ARG_NETWORK).map(|s| s.as_str())
+}
+
+// ------------------------------------------------------------------ price ----
+
+const ARG_PRICE_MEM: &str = "price-mem";
+
+fn arg_price_mem() -> Arg {
+    Arg::new(ARG_PRICE_MEM)
+        .long(ARG_PRICE_MEM)
+        .value_name("LOVELACE")
+        .help("Override the protocol's price of a single memory unit, possibly fractional (e.g. 0.0577).")
+        .action(ArgAction::Set)
+}
+
+const ARG_PRICE_STEPS: &str = "price-steps";
+
+fn arg_price_steps() -> Arg {
+    Arg::new(ARG_PRICE_STEPS)
+        .long(ARG_PRICE_STEPS)
+        .value_name("LOVELACE")
+        .help("Override the protocol's price of a single CPU step, possibly fractional (e.g. 0.0000721).")
+        .action(ArgAction::Set)
+}
+
+// --------------------------------------------------------------- proposal ----
+
+const ARG_PROPOSAL: &str = "proposal";
+
+fn arg_proposal() -> Arg {
+    Arg::new(ARG_PROPOSAL)
+        .long(ARG_PROPOSAL)
+        .short('p')
+        .value_name("TX_ID#IX")
+        .help("The proposal procedure identifier that's being voted on. Use multiple times to vote on several proposals at once.")
+        .action(ArgAction::Append)
+}
+
+const FLAG_ALLOW_UNCONFIRMED_PROPOSAL: &str = "allow-unconfirmed-proposal";
+
+fn flag_allow_unconfirmed_proposal() -> Arg {
+    Arg::new(FLAG_ALLOW_UNCONFIRMED_PROPOSAL)
+        .long(FLAG_ALLOW_UNCONFIRMED_PROPOSAL)
+        .help("Do not require the proposal to be on-chain already (e.g. still in the mempool).")
+        .action(ArgAction::SetTrue)
+}
+
+pub(crate) fn get_allow_unconfirmed_proposal(args: &ArgMatches) -> bool {
+    args.get_flag(FLAG_ALLOW_UNCONFIRMED_PROPOSAL)
+}
+
+const ARG_CONFIRM_PROPOSAL_HASH: &str = "confirm-proposal-hash";
+
+fn arg_confirm_proposal_hash() -> Arg {
+    Arg::new(ARG_CONFIRM_PROPOSAL_HASH)
+        .long(ARG_CONFIRM_PROPOSAL_HASH)
+        .value_name("HEX_STRING")
+        .help("The transaction id of the proposal, again. Fails when it doesn't match --proposal (or --proposal-from-tx); a guard against typos. Use once per distinct proposal transaction.")
+        .action(ArgAction::Append)
+}
+
+const ARG_PROPOSAL_FROM_TX: &str = "proposal-from-tx";
+
+fn arg_proposal_from_tx() -> Arg {
+    Arg::new(ARG_PROPOSAL_FROM_TX)
+        .long(ARG_PROPOSAL_FROM_TX)
+        .value_name("TX_ID")
+        .help("The transaction that submitted the proposal, in lieu of --proposal. The action index is derived from it.")
+        .action(ArgAction::Set)
+}
+
+const ARG_PROPOSAL_INDEX: &str = "proposal-index";
+
+fn arg_proposal_index() -> Arg {
+    Arg::new(ARG_PROPOSAL_INDEX)
+        .long(ARG_PROPOSAL_INDEX)
+        .value_name("UINT")
+        .requires(ARG_PROPOSAL_FROM_TX)
+        .help("Which of the proposals submitted by --proposal-from-tx to vote on. Only needed when there are several.")
+        .action(ArgAction::Set)
+}
+
+// When given a transaction rather than a full proposal id, the action index is derived from the
+// proposal procedures found in that transaction. Commands offering this must therefore make
+// --proposal and --proposal-from-tx a required group.
+pub(crate) async fn get_arg_proposals_or_lookup(
+    args: &ArgMatches,
+    network: &impl Backend,
+) -> Result<Vec<GovActionId>, Error> {
+    let Some(transaction_id) = args.get_one::<String>(ARG_PROPOSAL_FROM_TX) else {
+        return get_arg_proposals(args);
+    };
+
+    let transaction_id: Hash<32> = transaction_id
+        .parse()
+        .map_err(|e| ParseFailure::HexString(ARG_PROPOSAL_FROM_TX, e))?;
+
+    ensure_confirmed_proposal(&get_arg_proposal_confirmations(args)?, &transaction_id)?;
+
+    let proposals = network
+        .transaction_by_hash(&transaction_id.to_string())
+        .await?
+        .and_then(|tx| tx.transaction_body.proposal_procedures)
+        .map(|proposals| proposals.len())
+        .unwrap_or_default();
+
+    let index = args
+        .get_one::<String>(ARG_PROPOSAL_INDEX)
+        .map(|s| s.parse::<u32>())
+        .transpose()
+        .map_err(|e| ParseFailure::Int(ARG_PROPOSAL_INDEX, e))?;
+
+    let action_index = match index {
+        Some(ix) if (ix as usize) < proposals => ix,
+        Some(ix) => {
+            return Err(Error::ProposalNotFound {
+                proposal: format!("{transaction_id}#{ix}"),
+            })
+        }
+        None if proposals == 1 => 0,
+        None if proposals == 0 => {
+            return Err(Error::ProposalNotFound {
+                proposal: transaction_id.to_string(),
+            })
+        }
+        None => {
+            return Err(Error::AmbiguousProposal {
+                transaction: transaction_id.to_string(),
+                proposals,
+            })
+        }
+    };
+
+    Ok(vec![GovActionId {
+        transaction_id,
+        action_index,
+    }])
+}
+
+fn get_arg_proposal_confirmations(args: &ArgMatches) -> Result<Vec<Hash<32>>, ParseFailure> {
+    args.get_many::<String>(ARG_CONFIRM_PROPOSAL_HASH)
+        .unwrap_or_default()
+        .map(|confirmation| {
+            confirmation
+                .parse::<Hash<32>>()
+                .map_err(|e| ParseFailure::HexString(ARG_CONFIRM_PROPOSAL_HASH, e))
+        })
+        .collect()
+}
+
+// Without any confirmation, there's nothing to check against.
+fn ensure_confirmed_proposal(
+    confirmations: &[Hash<32>],
+    transaction_id: &Hash<32>,
+) -> Result<(), Error> {
+    if !confirmations.is_empty() && !confirmations.contains(transaction_id) {
+        return Err(Error::ProposalHashMismatch {
+            proposal: transaction_id.to_string(),
+            confirmation: confirmations
+                .iter()
+                .map(|h| h.to_string())
+                .collect::<Vec<_>>()
+                .join(", "),
+        });
+    }
+
+    Ok(())
+}
+
+pub(crate) fn get_arg_proposals(args: &ArgMatches) -> Result<Vec<GovActionId>, Error> {
+    let confirmations = get_arg_proposal_confirmations(args)?;
+
+    let mut proposals: Vec<GovActionId> = vec![];
+    for proposal in args.get_many::<String>(ARG_PROPOSAL).unwrap_or_default() {
+        let OutputReference(utxo_like) = proposal
+            .parse()
+            .map_err(|e| ParseFailure::OutputReference(ARG_PROPOSAL, e))?;
+
+        ensure_confirmed_proposal(&confirmations, &utxo_like.transaction_id)?;
+
+        // NOTE: Truncating would silently target another action; never an option.
+        let action_index = u32::try_from(utxo_like.index).map_err(|_| {
+            ParseFailure::LossyConversion(ARG_PROPOSAL, utxo_like.index.to_string())
+        })?;
+
+        let proposal = GovActionId {
+            transaction_id: utxo_like.transaction_id,
+            action_index,
+        };
+
+        if proposals.contains(&proposal) {
+            return Err(Error::DuplicateProposal {
+                proposal: format!("{}#{}", proposal.transaction_id, proposal.action_index),
+            });
+        }
+
+        proposals.push(proposal);
+    }
+
+    Ok(proposals)
+}
+
+// -------------------------------------------------------- protocol params ----
+
+const ARG_PROTOCOL_PARAMS: &str = "protocol-params";
+
+fn arg_protocol_params() -> Arg {
+    Arg::new(ARG_PROTOCOL_PARAMS)
+        .long(ARG_PROTOCOL_PARAMS)
+        .value_name("FILEPATH")
+        .conflicts_with(ARG_EPOCH)
+        .help("Use the protocol parameters from this file (e.g. from 'cardano-cli query protocol-parameters') instead of fetching them from the network. Cost models may be keyed by network name (e.g. costModels.preprod.PlutusV3).")
+        .action(ArgAction::Set)
+}
+
+pub(crate) fn has_arg_protocol_params(args: &ArgMatches) -> bool {
+    args.contains_id(ARG_PROTOCOL_PARAMS)
+}
+
+pub(crate) fn get_arg_protocol_params(
+    args: &ArgMatches,
+    network: Option<&str>,
+) -> Result<Option<ProtocolParameters>, Error> {
+    match args.get_one::<String>(ARG_PROTOCOL_PARAMS) {
+        None => Ok(None),
+        Some(path) => {
+            let path = PathBuf::from(path);
+            let content = fs::read(&path).map_err(|e| Error::FailedToReadFile(path.clone(), e))?;
+            serde_json::from_slice(&content)
+                .map_err(|e| e.to_string())
+                .and_then(|json| ProtocolParameters::from_json(&json, network))
+                .map(Some)
+                .map_err(|e| Error::Parse(ParseFailure::ProtocolParameters(ARG_PROTOCOL_PARAMS, e)))
+        }
+    }
+}
+
+// ----------------------------------------------------------------- quorum ----
+
+const ARG_QUORUM: &str = "quorum";
+
+fn arg_quorum() -> Arg {
+    Arg::new(ARG_QUORUM)
+        .long(ARG_QUORUM)
+        .short('q')
+        .value_name("UINT")
+        .help("Minimum number of delegates to authorize votes. Default to the total number of delegates (plenum).")
+        .action(ArgAction::Set)
+}
+
+// A quorum of zero would let anyone through; one above the number of delegates, no one.
+pub(crate) fn get_arg_quorum(args: &ArgMatches, delegates: usize) -> Result<usize, ParseFailure> {
+    let quorum = args
+        .get_one::<String>(ARG_QUORUM)
+        .map(|s| s.parse().map_err(|e| ParseFailure::Int(ARG_QUORUM, e)))
+        .transpose()?
+        .unwrap_or(delegates);
+
+    if quorum == 0 || quorum > delegates {
+        return Err(ParseFailure::OutOfRange(
+            ARG_QUORUM,
+            format!("{quorum} (expected between 1 and {delegates})"),
+        ));
+    }
+
+    Ok(quorum)
+}
+
+// --------------------------------------------------------------- redeemer ----
+
+const ARG_REDEEMER: &str = "redeemer";
+
+fn arg_redeemer() -> Arg {
+    Arg::new(ARG_REDEEMER)
+        .long(ARG_REDEEMER)
+        .value_name("TAG:INDEX=CBOR")
+        .help("Replace the redeemer's data at the given purpose (spend, mint, publish, withdraw, vote or propose) and index with some hex-encoded Plutus data. Repeatable.")
+        .action(ArgAction::Append)
+}
+
+fn get_arg_redeemers(args: &ArgMatches) -> Result<Vec<RedeemerOverride>, ParseFailure> {
+    args.get_many::<String>(ARG_REDEEMER)
+        .unwrap_or_default()
+        .map(|s| parse_redeemer_override(s).map_err(|e| ParseFailure::Redeemer(ARG_REDEEMER, e)))
+        .collect()
+}
+
+fn parse_redeemer_override(s: &str) -> Result<RedeemerOverride, String> {
+    let (key, data) = s
+        .split_once('=')
+        .ok_or_else(|| "expected a redeemer pointer and some data separated by '='".to_string())?;
+
+    let (tag, index) = key
+        .split_once(':')
+        .ok_or_else(|| "expected a purpose and an index separated by ':'".to_string())?;
+
+    let tag = match tag {
+        "spend" => RedeemerTag::Spend,
+        "mint" => RedeemerTag::Mint,
+        "publish" | "cert" => RedeemerTag::Cert,
+        "withdraw" | "reward" => RedeemerTag::Reward,
+        "vote" => RedeemerTag::Vote,
+        "propose" => RedeemerTag::Propose,
+        _ => return Err(format!("unknown redeemer purpose '{tag}'")),
+    };
+
+    let index = index
+        .parse()
+        .map_err(|e| format!("failed to decode redeemer index: {e:?}"))?;
+
+    let data = hex::decode(data).map_err(|e| format!("failed to decode data from hex: {e:?}"))?;
+
+    let data = cbor::decode(&data).map_err(|e| format!("failed to decode Plutus data: {e}"))?;
+
+    Ok(RedeemerOverride { tag, index, data })
+}
+
+// --------------------------------------------------------- refund address ----
+
+const ARG_REFUND_ADDRESS: &str = "refund-address";
+
+fn arg_refund_address() -> Arg {
+    Arg::new(ARG_REFUND_ADDRESS)
+        .long(ARG_REFUND_ADDRESS)
+        .value_name("BECH32")
+        .help("Send the reclaimed DRep deposit (and the contract's lovelace) to this address rather than to the change.")
+        .action(ArgAction::Set)
+}
+
+pub(crate) fn get_arg_refund_address(args: &ArgMatches) -> Result<Option<Address>, ParseFailure> {
+    args.get_one::<String>(ARG_REFUND_ADDRESS)
+        .map(|s| {
+            Address::from_bech32(s)
+                .map_err(|e| ParseFailure::Address(ARG_REFUND_ADDRESS, e.to_string()))
+        })
+        .transpose()
+}
+
+// ------------------------------------------------------------------ stake ----
+
+const ARG_WITH_STAKE: &str = "with-stake";
+
+fn arg_with_stake() -> Arg {
+    Arg::new(ARG_WITH_STAKE)
+        .long(ARG_WITH_STAKE)
+        .value_name("TX_ID#IX")
+        .conflicts_with(ARG_CONTRACT)
+        .help("A UTxO whose stake key also delegates its voting rights to the new DRep, in the same transaction.")
+        .action(ArgAction::Set)
+}
+
+pub(crate) fn get_arg_with_stake(
+    args: &ArgMatches,
+) -> Result<Option<OutputReference>, ParseFailure> {
+    args.get_one::<String>(ARG_WITH_STAKE)
+        .map(|s| {
+            s.parse()
+                .map_err(|e| ParseFailure::OutputReference(ARG_WITH_STAKE, e))
+        })
+        .transpose()
+}
+
+// -------------------------------------------------------- resolved inputs ----
+
+const ARG_DUMP_RESOLVED_INPUTS: &str = "dump-resolved-inputs";
+
+fn arg_dump_resolved_inputs() -> Arg {
+    Arg::new(ARG_DUMP_RESOLVED_INPUTS)
+        .long(ARG_DUMP_RESOLVED_INPUTS)
+        .value_name("FILEPATH")
+        .help("Write the inputs resolved while building to this file, for later use with --resolved-inputs.")
+        .action(ArgAction::Set)
+}
+
+const ARG_RESOLVED_INPUTS: &str = "resolved-inputs";
+
+fn arg_resolved_inputs() -> Arg {
+    Arg::new(ARG_RESOLVED_INPUTS)
+        .long(ARG_RESOLVED_INPUTS)
+        .value_name("FILEPATH")
+        .help("Resolve inputs from this file (as written with --dump-resolved-inputs, or a CBOR list of (input, output) pairs) instead of the network. Inputs missing from the file are an error.")
+        .action(ArgAction::Set)
+}
+
+pub(crate) fn get_arg_resolved_inputs(
+    args: &ArgMatches,
+) -> Result<Option<Vec<ResolvedInput>>, Error> {
+    match args.get_one::<String>(ARG_RESOLVED_INPUTS) {
+        None => Ok(None),
+        Some(path) => {
+            let path = PathBuf::from(path);
+            let content = fs::read(&path).map_err(|e| Error::FailedToReadFile(path.clone(), e))?;
+            match serde_json::from_slice(&content) {
+                Ok(json) => resolved_inputs_from_json(&json),
+                Err(_) => resolved_inputs_from_cbor(&content),
+            }
+            .map(Some)
+            .map_err(|e| Error::Parse(ParseFailure::ResolvedInputs(ARG_RESOLVED_INPUTS, e)))
+        }
+    }
+}
+
+// ------------------------------------------------------ simulate fee bump ----
+
+const ARG_SIMULATE_FEE_BUMP: &str = "simulate-fee-bump";
+
+fn arg_simulate_fee_bump() -> Arg {
+    Arg::new(ARG_SIMULATE_FEE_BUMP)
+        .long(ARG_SIMULATE_FEE_BUMP)
+        .value_name("PERCENT,...")
+        .value_delimiter(',')
+        .help("Instead of the transaction, report its fee when the fee coefficient and price of steps are raised by each of these percentages (e.g. 0,10,50,100). Deltas are relative to the first.")
+        .action(ArgAction::Append)
+}
+
+pub(crate) fn get_arg_simulate_fee_bump(args: &ArgMatches) -> Result<Vec<u64>, ParseFailure> {
+    args.get_many::<String>(ARG_SIMULATE_FEE_BUMP)
+        .unwrap_or_default()
+        .map(|s| {
+            s.parse()
+                .map_err(|e| ParseFailure::Int(ARG_SIMULATE_FEE_BUMP, e))
+        })
+        .collect()
+}
+
+// ----------------------------------------------------------------- strict ----
+
+const FLAG_STRICT: &str = "strict";
+
+fn flag_strict() -> Arg {
+    Arg::new(FLAG_STRICT)
+        .long(FLAG_STRICT)
+        .help("Compute execution costs on the exact decimal prices, like the ledger does, rather than with floats; and fail whenever that isn't possible.")
+        .action(ArgAction::SetTrue)
+}
+
+// ---------------------------------------------------------------- timings ----
+
+const FLAG_TIMINGS: &str = "timings";
+
+fn flag_timings() -> Arg {
+    Arg::new(FLAG_TIMINGS)
+        .long(FLAG_TIMINGS)
+        .help("Report how long each phase (provider queries, script evaluations, ...) took, on stderr.")
+        .action(ArgAction::SetTrue)
+}
+
+pub(crate) fn get_build_options(args: &ArgMatches) -> Result<BuildOptions, ParseFailure> {
+    Ok(BuildOptions {
+        timings: Timings::new(args.get_flag(FLAG_TIMINGS)),
+        warnings: Warnings::default(),
+        abort_on_warning: args.get_flag(FLAG_ABORT_ON_WARNING),
+        best_effort: args.get_flag(FLAG_BEST_EFFORT),
+        bundle: args.get_one::<String>(ARG_BUNDLE).map(PathBuf::from),
+        plan: args.get_one::<String>(ARG_PLAN).map(PathBuf::from),
+        explanation: Default::default(),
+        // NOTE: Only vote and delegate can explain themselves.
+        explain: matches!(args.try_get_one::<bool>(FLAG_EXPLAIN), Ok(Some(true))),
+        convergence_cache: args
+            .get_one::<String>(ARG_ASSUME_YES_CONVERGENCE)
+            .map(PathBuf::from),
+        era: get_arg_era(args),
+        debug_attempts: args
+            .get_one::<String>(ARG_DEBUG_ATTEMPTS)
+            .map(PathBuf::from),
+        dump_resolved_inputs: args
+            .get_one::<String>(ARG_DUMP_RESOLVED_INPUTS)
+            .map(PathBuf::from),
+        redeemer_overrides: get_arg_redeemers(args)?,
+        verify_on_chain_hash: args.get_flag(FLAG_VERIFY_ON_CHAIN_HASH),
+        encoding: get_arg_encoding(args),
+        out: get_arg_out(args),
+        donation: get_arg_donation(args)?,
+        keep_change_above: args
+            .get_one::<String>(ARG_KEEP_CHANGE_ABOVE)
+            .map(|s| {
+                s.parse()
+                    .map_err(|e| ParseFailure::Int(ARG_KEEP_CHANGE_ABOVE, e))
+            })
+            .transpose()?
+            .unwrap_or(0),
+        dump_fee_model: args.get_flag(FLAG_DUMP_FEE_MODEL),
+        collateral_only_ada: args.get_flag(FLAG_COLLATERAL_ONLY_ADA),
+        no_collateral_return: args.get_flag(FLAG_NO_COLLATERAL_RETURN),
+        token_prefix: get_arg_token_prefix(args)?,
+        validity_start: get_arg_validity_start(args)?,
+        // NOTE: Resolved later on, from the network.
+        ttl: None,
+        slots: Slots::default(),
+        double_check_eval: args.get_flag(FLAG_DOUBLE_CHECK_EVAL),
+        // NOTE: Only vote can be strict.
+        strict: matches!(args.try_get_one::<bool>(FLAG_STRICT), Ok(Some(true))),
+        max_fee: get_arg_max_fee(args)?,
+        max_attempts: get_arg_max_build_attempts(args)?,
+        change_address: get_arg_change_address(args)?,
+        expected_signers: get_arg_expected_signers(args)?,
+    })
+}
+
+// ----------------------------------------------------------- token prefix ----
+
+const ARG_TOKEN_PREFIX: &str = "token-prefix";
+
+fn arg_token_prefix() -> Arg {
+    Arg::new(ARG_TOKEN_PREFIX)
+        .long(ARG_TOKEN_PREFIX)
+        .value_name("STRING")
+        .default_value("gov_")
+        .help("The prefix of the contract's state token asset name, exactly 4 bytes. Must match what the validator expects.")
+        .action(ArgAction::Set)
+}
+
+// NOTE: Asset names are at most 32 bytes, 28 of which are taken by the rules' hash.
+pub(crate) fn get_arg_token_prefix(args: &ArgMatches) -> Result<Vec<u8>, ParseFailure> {
+    let prefix = args
+        .get_one::<String>(ARG_TOKEN_PREFIX)
+        .map(|s| s.as_bytes().to_vec())
+        .unwrap_or_else(|| GOVERNANCE_PREFIX.to_vec());
+
+    // NOTE: The validator compares the first 4 bytes of asset names with its own prefix.
+    if prefix.len() != 4 {
+        Err(ParseFailure::UnexpectedLength(
+            ARG_TOKEN_PREFIX,
+            prefix.len(),
+        ))
+    } else {
+        Ok(prefix)
+    }
+}
+
+// -------------------------------------------------------------------- ttl ----
+
+const FLAG_NO_AUTO_TTL: &str = "no-auto-ttl";
+
+fn flag_no_auto_ttl() -> Arg {
+    Arg::new(FLAG_NO_AUTO_TTL)
+        .long(FLAG_NO_AUTO_TTL)
+        .help("Do not bound the transaction's validity to the proposal's voting window.")
+        .action(ArgAction::SetTrue)
+}
+
+pub(crate) fn get_auto_ttl(args: &ArgMatches) -> bool {
+    !args.get_flag(FLAG_NO_AUTO_TTL)
+}
+
+const ARG_TTL: &str = "ttl";
+
+fn arg_ttl() -> Arg {
+    Arg::new(ARG_TTL)
+        .long(ARG_TTL)
+        .value_name("SLOT")
+        .help("The last slot at which the transaction is valid. When a command also bounds it (e.g. vote), the earliest wins.")
+        .action(ArgAction::Set)
+}
+
+const ARG_VALID_FOR: &str = "valid-for";
+
+fn arg_valid_for() -> Arg {
+    Arg::new(ARG_VALID_FOR)
+        .long(ARG_VALID_FOR)
+        .value_name("DURATION")
+        .conflicts_with(ARG_TTL)
+        .help("Like --ttl, but relative to the current tip; in seconds, or suffixed with s, m, h or d (e.g. 90m).")
+        .action(ArgAction::Set)
+}
+
+const ARG_VALID_FOR_EPOCHS: &str = "valid-for-epochs";
+
+fn arg_valid_for_epochs() -> Arg {
+    Arg::new(ARG_VALID_FOR_EPOCHS)
+        .long(ARG_VALID_FOR_EPOCHS)
+        .value_name("UINT")
+        .conflicts_with_all([ARG_TTL, ARG_VALID_FOR])
+        .help("Like --ttl, but until the end of the current epoch plus this many more (i.e. 0 for the current epoch only).")
+        .action(ArgAction::Set)
+}
+
+// NOTE: Slots last a second on every public network, so durations translate as-is.
+pub(crate) async fn get_arg_ttl(
+    args: &ArgMatches,
+    network: &impl Backend,
+) -> Result<Option<u64>, Error> {
+    if let Some(ttl) = args.get_one::<String>(ARG_TTL) {
+        return Ok(Some(
+            ttl.parse().map_err(|e| ParseFailure::Int(ARG_TTL, e))?,
+        ));
+    }
+
+    if let Some(duration) = args.get_one::<String>(ARG_VALID_FOR) {
+        let seconds =
+            parse_duration(duration).map_err(|e| ParseFailure::Duration(ARG_VALID_FOR, e))?;
+        return network
+            .tip()
+            .await?
+            .slot
+            .checked_add(seconds)
+            .map(Some)
+            .ok_or_else(|| {
+                ParseFailure::Duration(ARG_VALID_FOR, format!("{duration} is too long")).into()
+            });
+    }
+
+    match args.get_one::<String>(ARG_VALID_FOR_EPOCHS) {
+        Some(epochs) => {
+            let epochs: u64 = epochs
+                .parse()
+                .map_err(|e| ParseFailure::Int(ARG_VALID_FOR_EPOCHS, e))?;
+            let current = network.tip().await?.epoch;
+            let epoch = current.checked_add(epochs).ok_or_else(|| {
+                ParseFailure::OutOfRange(ARG_VALID_FOR_EPOCHS, epochs.to_string())
+            })?;
+            Ok(Some(network.epoch_boundary(epoch).await?))
+        }
+        None => Ok(None),
+    }
+}
+
+fn parse_duration(duration: &str) -> Result<u64, String> {
+    let (amount, unit) = match duration.find(|c: char| !c.is_ascii_digit()) {
+        Some(ix) => duration.split_at(ix),
+        None => (duration, "s"),
+    };
+
+    let multiplier = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86400,
+        _ => {
+            return Err(format!(
+                "unknown unit '{unit}', expected one of s, m, h or d"
+            ))
+        }
+    };
+
+    amount
+        .parse::<u64>()
+        .map_err(|e| e.to_string())?
+        .checked_mul(multiplier)
+        .ok_or_else(|| format!("{duration} is too long"))
+}
+
+// -------------------------------------------------------------- validator ----
+
+const ARG_VALIDATOR: &str = "validator";
+
+fn arg_validator() -> Arg {
+    Arg::new(ARG_VALIDATOR)
+        .long(ARG_VALIDATOR)
+        .short('v')
+        .value_name("HEX_STRING")
+        .help("The compiled validator code, hex-encoded. (e.g jq -r '.validators[0].compiledCode' plutus.json)")
+        .action(ArgAction::Set)
+}
+
+pub(crate) fn get_arg_validator(args: &ArgMatches) -> Result<Option<Bytes>, ParseFailure> {
+    args.get_one::<String>(ARG_VALIDATOR)
+        .map(|s| {
+            hex::decode(s)
+                .map(Bytes::from)
+                .map_err(|e| ParseFailure::HexString(ARG_VALIDATOR, e))
+        })
+        .transpose()
+}
+
+const ARG_VALIDATOR_HASH: &str = "validator-hash";
+
+fn arg_validator_hash() -> Arg {
+    Arg::new(ARG_VALIDATOR_HASH)
+        .long(ARG_VALIDATOR_HASH)
+        .value_name("HEX_STRING")
+        .conflicts_with(ARG_VALIDATOR)
+        .help("The validator's hash, hex-encoded; a shortcut for --validator when the script itself isn't needed.")
+        .action(ArgAction::Set)
+}
+
+// Commands that never witness the validator only need its hash, which may be given directly or
+// derived from the full script.
+pub(crate) fn get_arg_validator_hash(args: &ArgMatches) -> Result<Hash<28>, ParseFailure> {
+    match args.get_one::<String>(ARG_VALIDATOR_HASH) {
+        Some(s) => {
+            let bytes =
+                hex::decode(s).map_err(|e| ParseFailure::HexString(ARG_VALIDATOR_HASH, e))?;
+            if bytes.len() == 28 {
+                Ok(Hash::from(&bytes[..]))
+            } else {
+                Err(ParseFailure::UnexpectedLength(
+                    ARG_VALIDATOR_HASH,
+                    bytes.len(),
+                ))
+            }
+        }
+        None => {
+            let validator = get_arg_validator(args)?.unwrap();
+            let (validator_hash, _) = from_validator(validator.as_ref(), Network::Mainnet);
+            Ok(validator_hash)
+        }
+    }
+}
+
+const FLAG_VERIFY_ON_CHAIN_HASH: &str = "verify-on-chain-hash";
+
+fn flag_verify_on_chain_hash() -> Arg {
+    Arg::new(FLAG_VERIFY_ON_CHAIN_HASH)
+        .long(FLAG_VERIFY_ON_CHAIN_HASH)
+        .help("Check that the validator's address is indeed the one of the --contract UTxO, before building.")
+        .action(ArgAction::SetTrue)
+}
+
+// --------------------------------------------------------- validity start ----
+
+const ARG_VALIDITY_START: &str = "validity-start";
+
+fn arg_validity_start() -> Arg {
+    Arg::new(ARG_VALIDITY_START)
+        .long(ARG_VALIDITY_START)
+        .visible_alias("valid-from")
+        .value_name("SLOT")
+        .help("The slot from which the transaction is valid; for validators asserting on the lower bound of the validity range.")
+        .action(ArgAction::Set)
+}
+
+fn get_arg_validity_start(args: &ArgMatches) -> Result<Option<u64>, ParseFailure> {
+    args.get_one::<String>(ARG_VALIDITY_START)
+        .map(|s| {
+            s.parse()
+                .map_err(|e| ParseFailure::Int(ARG_VALIDITY_START, e))
+        })
+        .transpose()
+}
+
+// ------------------------------------------------------------------- vkey ----
+
+const ARG_VKEY: &str = "vkey";
+
+fn arg_vkey() -> Arg {
+    Arg::new(ARG_VKEY)
+        .long(ARG_VKEY)
+        .value_name("HEX_STRING|BECH32")
+        .required(true)
+        .help("An ed25519 verification key, possibly extended, either hex-encoded (raw or CBOR) or bech32-encoded.")
+        .action(ArgAction::Set)
+}
+
+pub(crate) fn get_arg_vkey(args: &ArgMatches) -> Result<PublicKey, ParseFailure> {
+    let str = args.get_one::<String>(ARG_VKEY).unwrap();
+
+    let bytes = if str.chars().all(|c| c.is_ascii_hexdigit()) {
+        hex::decode(str).map_err(|e| ParseFailure::HexString(ARG_VKEY, e))?
+    } else {
+        bech32::decode(str)
+            .map_err(|e| ParseFailure::Bech32(ARG_VKEY, e))?
+            .1
+    };
+
+    // NOTE: Keys from text envelopes are CBOR-serialised bytestrings, whose header is either
+    // 0x5820 (32 bytes) or 0x5840 (64 bytes, extended). Extended keys carry their chain code
+    // after the key, which isn't part of what gets hashed. Raw keys may well start with the same
+    // bytes, so the header only counts when the length that follows matches it.
+    let key = match &bytes[..] {
+        [0x58, 0x20, key @ ..] if key.len() == 32 => key,
+        [0x58, 0x40, key @ ..] if key.len() == 64 => key,
+        key => key,
+    };
+
+    match key.len() {
+        32 | 64 => Ok(PublicKey::try_from(&key[..PublicKey::SIZE]).unwrap()),
+        n => Err(ParseFailure::UnexpectedLength(ARG_VKEY, n)),
+    }
+}
+
+// ------------------------------------------------------------------- vote ----
+
+const FLAG_YES: &str = "yes";
+const FLAG_NO: &str = "no";
+const FLAG_ABSTAIN: &str = "abstain";
+
+// Choices pair with proposals in the order they're given on the command-line, whatever the flag;
+// so '--yes --no --yes' reads as three separate votes. A single choice applies to all proposals.
+pub(crate) fn get_arg_votes(
+    args: &ArgMatches,
+    proposals: Vec<GovActionId>,
+) -> Result<Vec<(GovActionId, Vote)>, Error> {
+    let mut choices = [
+        (FLAG_YES, Vote::Yes),
+        (FLAG_NO, Vote::No),
+        (FLAG_ABSTAIN, Vote::Abstain),
+    ]
+    .into_iter()
+    .flat_map(|(flag, choice)| {
+        args.indices_of(flag)
+            .into_iter()
+            .flatten()
+            .map(move |ix| (ix, choice.clone()))
+    })
+    .collect::<Vec<_>>();
+    choices.sort_by_key(|(ix, _)| *ix);
+
+    match choices.len() {
+        1 => {
+            let (_, choice) = choices.remove(0);
+            Ok(proposals
+                .into_iter()
+                .map(|proposal| (proposal, choice.clone()))
+                .collect())
+        }
+        n if n == proposals.len() => Ok(proposals
+            .into_iter()
+            .zip(choices.into_iter().map(|(_, choice)| choice))
+            .collect()),
+        n => Err(Error::VoteCountMismatch {
+            proposals: proposals.len(),
+            choices: n,
+        }),
+    }
+}
+
+fn flag_yes() -> Arg {
+    Arg::new(FLAG_YES)
+        .short('y')
+        .long(FLAG_YES)
+        .help("Approve the governance proposal")
+        .num_args(0)
+        .default_missing_value(FLAG_YES)
+        .action(ArgAction::Append)
+}
+
+fn flag_no() -> Arg {
+    Arg::new(FLAG_NO)
+        .short('n')
+        .long(FLAG_NO)
+        .help("Reject the governance proposal")
+        .num_args(0)
+        .default_missing_value(FLAG_NO)
+        .action(ArgAction::Append)
+}
+
+fn flag_abstain() -> Arg {
+    Arg::new(FLAG_ABSTAIN)
+        .long(FLAG_ABSTAIN)
+        .help("Abstain from the governance proposal voting")
+        .num_args(0)
+        .default_missing_value(FLAG_ABSTAIN)
+        .action(ArgAction::Append)
 }
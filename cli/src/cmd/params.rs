@@ -0,0 +1,24 @@
+//  This Source Code Form is subject to the terms of the Mozilla Public
+//  License, v. 2.0. If a copy of the MPL was not distributed with this
+//  file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use crate::{backend::Backend, cardano::ProtocolParameters, error::Error};
+use clap::Command;
+
+pub(crate) fn cmd() -> Command {
+    Command::new("params")
+        .about("Show the protocol parameters used to build transactions.")
+        .after_help(color_print::cstr!(
+            r#"<underline><bold>Notes:</bold></underline>
+  1. Parameters are shown in the same format as the <italic>cardano-cli</italic>, restricted to those relevant to this tool.
+  2. Those of a past epoch can be shown with <bold>--epoch</bold>.
+
+<underline><bold>Example:</bold></underline>
+  <bold>params</bold> > protocol-parameters.json
+"#              ))
+        .arg(super::arg_epoch())
+}
+
+pub(crate) async fn params(network: impl Backend) -> Result<ProtocolParameters, Error> {
+    network.protocol_parameters().await
+}
@@ -2,13 +2,14 @@
 //  License, v. 2.0. If a copy of the MPL was not distributed with this
 //  file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
-use crate::{cardano::Cardano, contract::*, pallas_extra::*};
-use clap::Command;
+use crate::{backend::Backend, contract::*, error::Error, pallas_extra::*};
+use clap::{ArgGroup, Command};
+use pallas_addresses::Address;
 use pallas_codec::utils::{NonZeroInt, Nullable, Set};
 use pallas_crypto::hash::Hash;
 use pallas_primitives::conway::{
-    Certificate, Language, PlutusV3Script, PostAlonzoTransactionOutput, PseudoTransactionOutput,
-    StakeCredential, TransactionBody, Tx, WitnessSet,
+    Certificate, PostAlonzoTransactionOutput, PseudoTransactionOutput, StakeCredential,
+    TransactionBody, Tx, Value,
 };
 
 pub(crate) fn cmd() -> Command {
@@ -19,6 +20,10 @@ pub(crate) fn cmd() -> Command {
   1. This operation effectively <underline>unregisters the delegate representative</underline>!
   2. The specified <bold>--administrator</bold> must reflect the signatories for the transaction, but not necessarily ALL administrators.
      Only those authorizing the transaction must be present. And, there must be enough signatories for a quorum.
+  3. The reclaimed DRep deposit, along with any asset held by the contract, goes back to the fuel's address, unless a
+     <bold>--refund-address</bold> is given.
+  4. Without <bold>--contract</bold>, the contract is looked up at the address of the given <bold>--validator</bold> (or <bold>--validator-hash</bold>).
+     This fails when there are several contracts at that address; pick one with <bold>--contract</bold> then.
 
 <underline><bold>Examples:</bold></underline>
   <bold>revoke</bold> \
@@ -27,30 +32,137 @@ pub(crate) fn cmd() -> Command {
     <bold>--fuel</bold> "ab5334d2db6f7909b511ee9c0f7181c7f4da515ba15f186d95caef0d91ac4a11#0"
 "#              ))
         .arg(super::arg_administrator())
-        .arg(super::arg_contract(true))
+        .arg(super::arg_contract(false))
+        .arg(super::arg_validator())
+        .arg(super::arg_validator_hash())
         .arg(super::arg_fuel())
+        .arg(super::arg_change_address())
+        .arg(super::arg_keep_change_above())
+        .arg(super::flag_collateral_only_ada())
+        .arg(super::flag_no_collateral_return())
+        .arg(super::arg_collateral_percent())
+        .arg(super::arg_refund_address())
+        .arg(super::arg_token_prefix())
+        .arg(super::arg_fee_constant())
+        .arg(super::arg_fee_coefficient())
+        .arg(super::arg_price_mem())
+        .arg(super::arg_price_steps())
+        .arg(super::flag_dump_fee_model())
+        .arg(super::arg_max_fee())
+        .arg(super::arg_assume_yes_convergence())
+        .arg(super::arg_redeemer())
+        .arg(super::arg_bundle())
+        .arg(super::arg_plan())
+        .arg(super::arg_debug_attempts())
+        .arg(super::arg_max_build_attempts())
+        .arg(super::arg_expected_signers())
+        .arg(super::flag_double_check_eval())
+        .arg(super::arg_epoch())
+        .arg(super::arg_protocol_params())
+        .arg(super::arg_era())
+        .arg(super::arg_encoding())
+        .arg(super::arg_out())
+        .arg(super::arg_dump_resolved_inputs())
+        .arg(super::arg_resolved_inputs())
+        .arg(super::arg_network())
+        .arg(super::arg_simulate_fee_bump())
+        .arg(super::flag_best_effort())
+        .arg(super::flag_timings())
+        .arg(super::flag_abort_on_warning())
+        .arg(super::flag_verify_on_chain_hash())
+        .arg(super::arg_validity_start())
+        .arg(super::arg_ttl())
+        .arg(super::arg_valid_for())
+        .arg(super::arg_valid_for_epochs())
+        .group(ArgGroup::new("source")
+            .args(["contract", "validator", "validator-hash"])
+            .multiple(false)
+            .required(true)
+        )
 }
 
 pub(crate) async fn revoke(
-    network: Cardano,
+    network: &impl Backend,
     administrators: Vec<Hash<28>>,
     OutputReference(contract): OutputReference,
-    OutputReference(fuel): OutputReference,
-) -> Tx {
-    let (validator, validator_hash, _) =
-        recover_validator(&network, &contract.transaction_id).await;
+    fuels: Vec<OutputReference>,
+    refund_address: Option<Address>,
+    options: &BuildOptions,
+) -> Result<Tx, Error> {
+    let (validator, validator_hash, validator_address) =
+        recover_validator(network, &contract.transaction_id).await?;
 
-    let params = network.protocol_parameters().await;
+    let fuels = fuels
+        .into_iter()
+        .map(|OutputReference(fuel)| fuel)
+        .collect::<Vec<_>>();
+    let fuel = fuels[0].clone();
 
-    let resolved_inputs = network.resolve_many(&[&fuel, &contract]).await;
+    for fuel in fuels.iter() {
+        super::ensure_unspent_fuel(network, fuel).await?;
+    }
+
+    let params = network.protocol_parameters().await?;
+
+    let resolved_inputs = network
+        .resolve_many(&fuels.iter().chain([&contract]).collect::<Vec<_>>())
+        .await?;
     let fuel_output = expect_post_alonzo(&resolved_inputs[0].output);
-    let contract_output = expect_post_alonzo(&resolved_inputs[1].output);
+    let change_address = super::change_address(network, options, &fuel_output.address)?;
+    let contract_output = expect_post_alonzo(&resolved_inputs[fuels.len()].output);
+
+    if options.verify_on_chain_hash {
+        ensure_validator_address(&contract, &validator_address, &contract_output.address)?;
+    }
+
+    let asset_name = expect_contract_token(
+        &contract,
+        &contract_output.value,
+        &validator_hash,
+        &options.token_prefix,
+    )?;
+    let datums = non_empty_set(spent_datums(network, contract_output).await?);
+
+    // NOTE: Any asset held in custody by the contract goes along with the refund.
+    let custody = assets_except(&contract_output.value, &validator_hash, &asset_name);
+
+    let refund = params.drep_deposit + lovelace_of(&contract_output.value);
+
+    let refund_output = refund_address
+        .map(|address| {
+            super::ensure_network(network, &address)?;
 
-    let asset_name =
-        find_contract_token(&contract_output.value).expect("no state token in contract utxo?");
+            let output = PostAlonzoTransactionOutput {
+                address: address.to_vec().into(),
+                value: value_add_assets(Value::Coin(refund), &custody),
+                datum_option: None,
+                script_ref: None,
+            };
+
+            let minimum = lovelace_of(
+                &new_min_value_output(params.min_utxo_deposit_coefficient, |lovelace| {
+                    PostAlonzoTransactionOutput {
+                        value: value_add_assets(Value::Coin(lovelace), &custody),
+                        ..output.clone()
+                    }
+                })
+                .value,
+            );
+
+            if refund < minimum {
+                return Err(Error::RefundBelowMinimum {
+                    lovelace: refund,
+                    minimum,
+                });
+            }
+
+            Ok(output)
+        })
+        .transpose()?;
 
     build_transaction(
         &BuildParams::from(&params),
+        options,
         &resolved_inputs[..],
         |fee, ex_units| {
             let mut redeemers = vec![];
@@ -58,10 +170,20 @@ pub(crate) async fn revoke(
             let mint = singleton_assets(
                 validator_hash,
                 &[(asset_name.clone(), NonZeroInt::try_from(-1).unwrap())],
-            );
+            )?;
             redeemers.push(Redeemer::mint(0, void(), ex_units[0]));
 
-            let mut inputs = vec![contract.clone(), fuel.clone()];
+            // NOTE: Without a refund output, the refund goes to the change and thus pays for fees.
+            let needed = if refund_output.is_some() {
+                fee
+            } else {
+                fee.saturating_sub(refund)
+            };
+            let (fuel_inputs, fuel_value) =
+                select_fuel(&resolved_inputs[..fuels.len()], needed, &[]);
+
+            let mut inputs = vec![contract.clone()];
+            inputs.extend(fuel_inputs);
             inputs.sort();
             redeemers.push(Redeemer::spend(
                 (&inputs[..], &contract),
@@ -69,32 +191,39 @@ pub(crate) async fn revoke(
                 ex_units[1],
             ));
 
-            let outputs = vec![
+            let mut outputs = vec![
                 // Change
                 PostAlonzoTransactionOutput {
-                    address: fuel_output.address.clone(),
-                    value: value_subtract_lovelace(
-                        value_add_lovelace(
-                            fuel_output.value.clone(),
-                            params.drep_deposit + lovelace_of(&contract_output.value),
-                        ),
+                    address: change_address.clone(),
+                    value: change_value(
+                        if refund_output.is_some() {
+                            fuel_value
+                        } else {
+                            value_add_assets(value_add_lovelace(fuel_value, refund), &custody)
+                        },
                         fee,
-                    )
-                    .expect("not enough fuel"),
+                    )?,
                     datum_option: None,
                     script_ref: None,
                 },
             ];
 
-            let total_collateral = (fee as f64 * params.collateral_percent).ceil() as u64;
+            // Refund
+            if let Some(ref refund_output) = refund_output {
+                outputs.push(refund_output.clone());
+            }
 
-            let collateral_return = PostAlonzoTransactionOutput {
-                address: fuel_output.address.clone(),
-                value: value_subtract_lovelace(fuel_output.value.clone(), total_collateral)
-                    .expect("not enough fuel"),
-                datum_option: None,
-                script_ref: None,
-            };
+            let fee = fold_dust_change(
+                &mut outputs,
+                0,
+                fee,
+                params.min_utxo_deposit_coefficient,
+                options.keep_change_above,
+            );
+
+            let total_collateral = total_collateral(fee, params.collateral_percent);
+
+            let collateral_return = collateral_return(&fuel, fuel_output, total_collateral)?;
 
             let certificates = vec![Certificate::UnRegDRepCert(
                 StakeCredential::Scripthash(validator_hash),
@@ -103,8 +232,8 @@ pub(crate) async fn revoke(
             redeemers.push(Redeemer::publish(0, void(), ex_units[2]));
 
             // ----- Put it all together
-            let redeemers = non_empty_pairs(redeemers).unwrap();
-            Tx {
+            let redeemers = required_pairs("redeemers", options.override_redeemers(redeemers))?;
+            Ok(Tx {
                 transaction_body: TransactionBody {
                     inputs: Set::from(inputs),
                     network_id: Some(from_network(network.network_id())),
@@ -119,21 +248,17 @@ pub(crate) async fn revoke(
                     script_data_hash: Some(
                         script_integrity_hash(
                             Some(&redeemers),
-                            None,
-                            &[(Language::PlutusV3, &params.cost_model_v3[..])],
+                            datums.as_ref(),
+                            &[(options.era.plutus_language(), &params.cost_model_v3[..])],
                         )
                         .unwrap(),
                     ),
                     ..default_transaction_body()
                 },
-                transaction_witness_set: WitnessSet {
-                    redeemer: Some(redeemers.into()),
-                    plutus_v3_script: non_empty_set(vec![PlutusV3Script(validator.clone())]),
-                    ..default_witness_set()
-                },
+                transaction_witness_set: new_witness_set(redeemers, datums.clone(), &[&validator]),
                 success: true,
                 auxiliary_data: Nullable::Null,
-            }
+            })
         },
     )
 }
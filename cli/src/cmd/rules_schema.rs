@@ -0,0 +1,72 @@
+//  This Source Code Form is subject to the terms of the Mozilla Public
+//  License, v. 2.0. If a copy of the MPL was not distributed with this
+//  file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use crate::contract::GOVERNANCE_PREFIX;
+use clap::Command;
+
+pub(crate) fn cmd() -> Command {
+    Command::new("rules-schema")
+        .about("Describe the on-chain rules datum, and how state token names derive from it.")
+        .after_help(color_print::cstr!(
+            r#"<underline><bold>Notes:</bold></underline>
+  1. The schema follows the conventions of CIP-0057 (Plutus blueprints): constructor indexes start at 0,
+     which corresponds to CBOR tag 121.
+  2. This command doesn't require any network access.
+
+<underline><bold>Example:</bold></underline>
+  <bold>rules-schema</bold>
+"#
+        ))
+}
+
+// The wire format of what 'build_rules' produces; to be kept in sync with it, so that indexers
+// and UIs don't have to reverse-engineer it.
+pub(crate) fn rules_schema() -> serde_json::Value {
+    let signatures = serde_json::json!({
+        "title": "scripts",
+        "dataType": "list",
+        "items": { "$ref": "#/definitions/Signature" },
+    });
+
+    serde_json::json!({
+        "title": "Rules",
+        "description": "A multisig script over the delegates; 'AnyOf' for a quorum of one, 'AtLeast' otherwise. Delegates are listed in the order they were given.",
+        "anyOf": [
+            {
+                "title": "AnyOf",
+                "dataType": "constructor",
+                "index": 2,
+                "fields": [signatures],
+            },
+            {
+                "title": "AtLeast",
+                "dataType": "constructor",
+                "index": 3,
+                "fields": [
+                    { "title": "required", "dataType": "integer" },
+                    signatures,
+                ],
+            },
+        ],
+        "definitions": {
+            "Signature": {
+                "title": "Signature",
+                "dataType": "constructor",
+                "index": 0,
+                "fields": [
+                    {
+                        "title": "key_hash",
+                        "dataType": "bytes",
+                        "minLength": 28,
+                        "maxLength": 28,
+                    },
+                ],
+            },
+        },
+        "assetName": {
+            "prefix": hex::encode(GOVERNANCE_PREFIX),
+            "suffix": "blake2b-224 digest of the CBOR-serialised rules",
+        },
+    })
+}
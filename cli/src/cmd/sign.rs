@@ -0,0 +1,213 @@
+//  This Source Code Form is subject to the terms of the Mozilla Public
+//  License, v. 2.0. If a copy of the MPL was not distributed with this
+//  file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use crate::{
+    error::Error,
+    pallas_extra::{body_hash, Era},
+};
+use clap::{Arg, ArgAction, ArgGroup, ArgMatches, Command};
+use indoc::formatdoc;
+use pallas_codec::{minicbor as cbor, utils::NonEmptySet};
+use pallas_crypto::key::ed25519::SecretKey;
+use pallas_primitives::conway::{MintedTx, PseudoTx, VKeyWitness};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+const ARG_TX: &str = "tx";
+
+const ARG_SIGNING_KEY: &str = "signing-key";
+
+pub(crate) fn cmd() -> Command {
+    Command::new("sign")
+        .about("Attach verification key witnesses to a transaction.")
+        .after_help(color_print::cstr!(
+            r#"<underline><bold>Notes:</bold></underline>
+  1. This command doesn't require any network access.
+  2. Signing keys are text envelopes of type <italic>PaymentSigningKeyShelley_ed25519</italic>, as produced by the
+     <italic>cardano-cli</italic> (or by <bold>generate-key</bold>).
+  3. Existing witnesses are kept, so a transaction may be signed by each party in turn. Signing twice with the
+     same key replaces the former witness.
+  4. With <bold>--plan</bold>, the transaction approved in a review plan (see <bold>--plan</bold> on other commands) is signed. When
+     <bold>--tx</bold> is given as well, it must have the same body as the approved one, or nothing gets signed.
+
+<underline><bold>Example:</bold></underline>
+  <bold>sign</bold> \
+    <bold>--tx</bold> vote.tx \
+    <bold>--signing-key</bold> delegate.sk \
+    <bold>--out</bold> vote.signed
+"#
+        ))
+        .arg(arg_tx().required(false))
+        .arg(
+            super::arg_plan()
+                .help("A review plan (as written by --plan on other commands), whose transaction is to be signed.")
+        )
+        .arg(
+            Arg::new(ARG_SIGNING_KEY)
+                .long(ARG_SIGNING_KEY)
+                .value_name("FILEPATH")
+                .required(true)
+                .help("A signing key to witness the transaction with. Use multiple times for multiple signatories.")
+                .action(ArgAction::Append),
+        )
+        .arg(super::arg_out())
+        .group(ArgGroup::new("source")
+            .args([ARG_TX, super::ARG_PLAN])
+            .multiple(true)
+            .required(true)
+        )
+}
+
+pub(super) fn arg_tx() -> Arg {
+    Arg::new(ARG_TX)
+        .long(ARG_TX)
+        .value_name("FILEPATH|HEX_STRING")
+        .required(true)
+        .help("A transaction, as a text envelope file (e.g. from the cardano-cli) or as hex-encoded CBOR.")
+        .action(ArgAction::Set)
+}
+
+pub(crate) fn get_arg_tx(args: &ArgMatches) -> Result<Vec<u8>, Error> {
+    let tx = args.get_one::<String>(ARG_TX).unwrap();
+    let tx_file = Path::new(tx);
+    if tx_file.is_file() {
+        super::verify::read_transaction(tx_file)
+    } else {
+        super::verify::parse_transaction(tx)
+            .map_err(|e| Error::MalformedTransaction(tx_file.to_path_buf(), e))
+    }
+}
+
+// The transaction approved in a plan; checked against the one given with --tx, if any.
+pub(crate) fn get_arg_tx_or_plan(args: &ArgMatches) -> Result<Vec<u8>, Error> {
+    let Some(plan) = args.get_one::<String>(super::ARG_PLAN) else {
+        return get_arg_tx(args);
+    };
+
+    let plan_file = Path::new(plan);
+    let malformed = |e: String| Error::MalformedPlan(plan_file.to_path_buf(), e);
+
+    let content = fs::read_to_string(plan_file)
+        .map_err(|e| Error::FailedToReadFile(plan_file.to_path_buf(), e))?;
+
+    let plan = serde_json::from_str::<serde_json::Value>(&content)
+        .map_err(|e| malformed(e.to_string()))?;
+
+    let approved = plan["transaction"]["cborHex"]
+        .as_str()
+        .ok_or_else(|| malformed("missing 'transaction.cborHex'".to_string()))?;
+    let approved = hex::decode(approved).map_err(|e| malformed(e.to_string()))?;
+
+    // NOTE: A plan edited after the fact no longer hashes to what was reviewed.
+    let expected = plan["transaction"]["bodyHash"]
+        .as_str()
+        .ok_or_else(|| malformed("missing 'transaction.bodyHash'".to_string()))?;
+    let ensure_approved = |tx: &[u8]| {
+        let tx: MintedTx = cbor::decode(tx)
+            .map_err(|e| Error::MalformedTransaction(PathBuf::from(ARG_TX), e.to_string()))?;
+        let actual = body_hash(&tx).to_string();
+        if actual != expected {
+            return Err(Error::PlanMismatch {
+                expected: expected.to_string(),
+                actual,
+            });
+        }
+        Ok(())
+    };
+
+    ensure_approved(&approved)?;
+
+    if args.contains_id(ARG_TX) {
+        let tx = get_arg_tx(args)?;
+        ensure_approved(&tx)?;
+        return Ok(tx);
+    }
+
+    Ok(approved)
+}
+
+pub(crate) fn get_arg_signing_keys(args: &ArgMatches) -> Result<Vec<SecretKey>, Error> {
+    args.get_many::<String>(ARG_SIGNING_KEY)
+        .unwrap_or_default()
+        .map(|path| read_signing_key(Path::new(path)))
+        .collect()
+}
+
+// Keys are CBOR-serialised as plain bytestrings of 32 bytes (i.e. 0x5820 + key).
+fn read_signing_key(path: &Path) -> Result<SecretKey, Error> {
+    let malformed = |e: String| Error::MalformedSigningKey(path.to_path_buf(), e);
+
+    let content =
+        fs::read_to_string(path).map_err(|e| Error::FailedToReadFile(path.to_path_buf(), e))?;
+
+    let envelope = serde_json::from_str::<serde_json::Value>(&content)
+        .map_err(|e| malformed(e.to_string()))?;
+
+    let cbor_hex = envelope
+        .get("cborHex")
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| malformed("missing 'cborHex' in text envelope".to_string()))?;
+
+    let key: [u8; SecretKey::SIZE] = cbor_hex
+        .strip_prefix("5820")
+        .and_then(|key| hex::decode(key).ok())
+        .and_then(|key| key.try_into().ok())
+        .ok_or_else(|| malformed("expected a 32-byte ed25519 key".to_string()))?;
+
+    Ok(SecretKey::from(key))
+}
+
+pub(crate) fn sign(tx: &[u8], signing_keys: &[SecretKey]) -> Result<String, Error> {
+    let tx: MintedTx = cbor::decode(tx)
+        .map_err(|e| Error::MalformedTransaction(PathBuf::from(ARG_TX), e.to_string()))?;
+
+    // NOTE: Like the body hash, the script integrity hash is over the original redeemers and
+    // datums. Hence, only the vkey witnesses are re-encoded; everything else is written back as-is.
+    let body_hash = body_hash(&tx);
+
+    let mut witness_set = (*tx.transaction_witness_set).clone();
+
+    let mut witnesses = witness_set
+        .vkeywitness
+        .map(|witnesses| witnesses.to_vec())
+        .unwrap_or_default();
+
+    for signing_key in signing_keys {
+        let vkey = signing_key.public_key();
+        let signature = signing_key.sign(body_hash);
+        witnesses.retain(|witness| witness.vkey[..] != vkey.as_ref()[..]);
+        witnesses.push(VKeyWitness {
+            vkey: Vec::from(vkey.as_ref()).into(),
+            signature: Vec::from(signature.as_ref()).into(),
+        });
+    }
+
+    witness_set.vkeywitness = NonEmptySet::try_from(witnesses).ok();
+
+    let mut buf = Vec::new();
+    cbor::encode(
+        &PseudoTx {
+            transaction_body: tx.transaction_body,
+            transaction_witness_set: witness_set,
+            success: tx.success,
+            auxiliary_data: tx.auxiliary_data,
+        },
+        &mut buf,
+    )
+    .unwrap();
+
+    Ok(formatdoc! {
+        r#"{{
+          "type": "{}",
+          "description": "{}",
+          "cborHex": "{}"
+        }}
+        "#,
+        Era::default().signed_envelope_type(),
+        Era::default().envelope_description(),
+        hex::encode(&buf),
+    })
+}
@@ -0,0 +1,185 @@
+//  This Source Code Form is subject to the terms of the Mozilla Public
+//  License, v. 2.0. If a copy of the MPL was not distributed with this
+//  file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use crate::{backend::Backend, cardano::drep_id, contract::*, error::Error, pallas_extra::*};
+use clap::Command;
+use pallas_addresses::{ShelleyAddress, ShelleyDelegationPart, ShelleyPaymentPart};
+use pallas_codec::{
+    minicbor as cbor,
+    utils::{NonEmptyKeyValuePairs, Nullable, Set},
+};
+use pallas_crypto::hash::Hash;
+use pallas_primitives::conway::{
+    ExUnits, GovActionId, PostAlonzoTransactionOutput, TransactionBody, TransactionInput,
+    TransactionOutput, Tx, Value, Vote, Voter, VotingProcedure,
+};
+use uplc::{
+    machine::cost_model::ExBudget,
+    tx::{self, eval_phase_two, ResolvedInput},
+};
+
+pub(crate) fn cmd() -> Command {
+    Command::new("simulate-quorum")
+        .about("Check whether a set of delegates would be allowed to vote, without voting.")
+        .after_help(color_print::cstr!(
+            r#"<underline><bold>Notes:</bold></underline>
+  1. A representative vote is built and evaluated locally against the contract's validator and current rules. Nothing is submitted.
+  2. The vote spends a fictitious input and targets a fictitious proposal; only the authorization is being simulated.
+
+<underline><bold>Example:</bold></underline>
+  <bold>simulate-quorum</bold> \
+    <bold>--contract</bold> "8d5726c0e7cb207a3f5881d29a7ceba71f578c2165a2261340c242bdba6875dd#0" \
+    <bold>--delegate</bold> 000000000000000000000000000000000000000000000000000a11ce \
+    <bold>--delegate</bold> 00000000000000000000000000000000000000000000000000000b0b
+"#              ))
+        .arg(super::arg_contract(true))
+        .arg(super::arg_delegate().required(true))
+        .arg(super::arg_token_prefix())
+}
+
+pub(crate) struct Simulation {
+    drep_id: String,
+    rules: serde_json::Value,
+    outcome: Result<ExUnits, (String, Vec<String>)>,
+}
+
+impl Simulation {
+    pub fn to_json(&self) -> serde_json::Value {
+        match self.outcome {
+            Ok(ExUnits { mem, steps }) => serde_json::json!({
+                "drepId": self.drep_id,
+                "rules": self.rules,
+                "accepted": true,
+                "exUnits": { "mem": mem, "steps": steps },
+            }),
+            Err((ref error, ref traces)) => serde_json::json!({
+                "drepId": self.drep_id,
+                "rules": self.rules,
+                "accepted": false,
+                "error": error,
+                "traces": traces,
+            }),
+        }
+    }
+}
+
+pub(crate) async fn simulate_quorum(
+    network: impl Backend,
+    delegates: Vec<Hash<28>>,
+    OutputReference(contract): OutputReference,
+    prefix: &[u8],
+) -> Result<Simulation, Error> {
+    let (validator, validator_hash, _) =
+        recover_validator(&network, &contract.transaction_id).await?;
+
+    let resolved_contract =
+        network
+            .resolve(&contract)
+            .await
+            .ok_or_else(|| Error::NoStateToken {
+                utxo: OutputReference(contract.clone()).to_string(),
+            })?;
+
+    let (rules, _) = recover_rules(
+        &network,
+        &validator_hash,
+        &contract,
+        &expect_post_alonzo(&resolved_contract.output).value,
+        prefix,
+    )
+    .await?;
+
+    // NOTE: The validator only ever looks at the signatories and its own rules when voting. So
+    // the rest of the transaction merely needs to be well-formed.
+    let fuel = TransactionInput {
+        transaction_id: Hash::new([0; 32]),
+        index: 0,
+    };
+
+    let fuel_output = PostAlonzoTransactionOutput {
+        address: ShelleyAddress::new(
+            network.network_id(),
+            ShelleyPaymentPart::key_hash(Hash::new([0; 28])),
+            ShelleyDelegationPart::Null,
+        )
+        .to_vec()
+        .into(),
+        value: Value::Coin(100_000_000),
+        datum_option: None,
+        script_ref: None,
+    };
+
+    let proposal_id = GovActionId {
+        transaction_id: Hash::new([0; 32]),
+        action_index: 0,
+    };
+
+    // Generous enough for any reasonable validator, as per mainnet's per-transaction limits.
+    let budget = ExUnits {
+        mem: 14_000_000,
+        steps: 10_000_000_000,
+    };
+
+    let redeemers = required_pairs("redeemers", vec![Redeemer::vote(0, rules.clone(), budget)])?;
+
+    let tx = Tx {
+        transaction_body: TransactionBody {
+            inputs: Set::from(vec![fuel.clone()]),
+            reference_inputs: non_empty_set(vec![contract.clone()]),
+            network_id: Some(from_network(network.network_id())),
+            outputs: into_outputs(vec![fuel_output.clone()]),
+            voting_procedures: non_empty_pairs(vec![(
+                Voter::DRepScript(validator_hash),
+                NonEmptyKeyValuePairs::Def(vec![(
+                    proposal_id,
+                    VotingProcedure {
+                        vote: Vote::Abstain,
+                        anchor: Nullable::Null,
+                    },
+                )]),
+            )]),
+            required_signers: non_empty_set(delegates.clone()),
+            ..default_transaction_body()
+        },
+        transaction_witness_set: new_witness_set(redeemers, None, &[&validator]),
+        success: true,
+        auxiliary_data: Nullable::Null,
+    };
+
+    let mut serialized_tx = Vec::new();
+    cbor::encode(&tx, &mut serialized_tx).unwrap();
+    let minted_tx = cbor::decode(&serialized_tx).unwrap();
+
+    let resolved_inputs = [
+        ResolvedInput {
+            input: fuel,
+            output: TransactionOutput::PostAlonzo(fuel_output),
+        },
+        resolved_contract,
+    ];
+
+    let outcome = eval_phase_two(
+        &minted_tx,
+        &resolved_inputs,
+        None,
+        Some(&ExBudget {
+            mem: budget.mem as i64,
+            cpu: budget.steps as i64,
+        }),
+        &network.slots().into(),
+        false,
+        |_| (),
+    )
+    .map(|redeemers| redeemers[0].ex_units)
+    .map_err(|e| match e {
+        tx::error::Error::Machine(_, _, ref traces) => (e.to_string(), traces.clone()),
+        _ => (e.to_string(), vec![]),
+    });
+
+    Ok(Simulation {
+        drep_id: drep_id(&validator_hash),
+        rules: super::explain_rules(&rules, &delegates[..]),
+        outcome,
+    })
+}
@@ -0,0 +1,110 @@
+//  This Source Code Form is subject to the terms of the Mozilla Public
+//  License, v. 2.0. If a copy of the MPL was not distributed with this
+//  file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use crate::{cardano::http_client, error::Error, pallas_extra::body_hash};
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use pallas_codec::minicbor as cbor;
+use pallas_crypto::hash::Hash;
+use pallas_primitives::conway::MintedTx;
+use std::{env, path::PathBuf};
+
+const ARG_OGMIOS_URL: &str = "ogmios-url";
+
+const ENV_OGMIOS_URL: &str = "OGMIOS_URL";
+
+const DEFAULT_OGMIOS_URL: &str = "http://localhost:1337";
+
+pub(crate) fn cmd() -> Command {
+    Command::new("submit")
+        .about("Submit a signed transaction to the network, through Ogmios.")
+        .after_help(color_print::cstr!(
+            r#"<underline><bold>Notes:</bold></underline>
+  1. Unlike other commands, this one doesn't use <italic>Blockfrost</italic>, but an <italic>Ogmios</italic> (v6+) server instead.
+  2. The server is taken from <bold>--ogmios-url</bold>, or else from the <bold>OGMIOS_URL</bold> environment variable, or else
+     defaults to <italic>http://localhost:1337</italic>.
+  3. The transaction id is printed on success. On failure, Ogmios' error is printed as-is; unless the transaction
+     spends unknown (e.g. already spent) inputs, which are then reported as such.
+
+<underline><bold>Example:</bold></underline>
+  <bold>submit</bold> \
+    <bold>--tx</bold> vote.signed \
+    <bold>--ogmios-url</bold> http://localhost:1337
+"#
+        ))
+        .arg(super::sign::arg_tx())
+        .arg(
+            Arg::new(ARG_OGMIOS_URL)
+                .long(ARG_OGMIOS_URL)
+                .value_name("URL")
+                .help("The Ogmios server to submit to. Defaults to the OGMIOS_URL environment variable.")
+                .action(ArgAction::Set),
+        )
+}
+
+pub(crate) fn get_arg_ogmios_url(args: &ArgMatches) -> String {
+    match args.get_one::<String>(ARG_OGMIOS_URL) {
+        Some(url) => url.to_string(),
+        None => env::var(ENV_OGMIOS_URL)
+            .ok()
+            .filter(|url| !url.is_empty())
+            .unwrap_or_else(|| DEFAULT_OGMIOS_URL.to_string()),
+    }
+}
+
+pub(crate) async fn submit(tx: &[u8], ogmios_url: &str) -> Result<Hash<32>, Error> {
+    let minted_tx: MintedTx = cbor::decode(tx)
+        .map_err(|e| Error::MalformedTransaction(PathBuf::from("tx"), e.to_string()))?;
+
+    let transaction_id = body_hash(&minted_tx);
+
+    let response = http_client(false)
+        .post(ogmios_url)
+        .json(&serde_json::json!({
+            "jsonrpc": "2.0",
+            "method": "submitTransaction",
+            "params": {
+                "transaction": {
+                    "cbor": hex::encode(tx),
+                },
+            },
+            "id": transaction_id.to_string(),
+        }))
+        .send()
+        .await
+        .map_err(|e| Error::ProviderUnavailable(e.to_string()))?
+        .json::<serde_json::Value>()
+        .await
+        .map_err(|e| Error::ProviderUnavailable(e.to_string()))?;
+
+    // NOTE: Ogmios replies with either a 'result' or an 'error', regardless of the HTTP status.
+    match response.get("error") {
+        Some(error) => Err(spent_inputs(error)
+            .map(|input| Error::FuelAlreadySpent { input })
+            .unwrap_or_else(|| Error::SubmissionRejected(error.clone()))),
+        None => Ok(transaction_id),
+    }
+}
+
+const UNKNOWN_OUTPUT_REFERENCES: i64 = 3117;
+
+// Inputs the ledger doesn't know (or no longer knows) of; most likely, fuel spent in the meantime.
+fn spent_inputs(error: &serde_json::Value) -> Option<String> {
+    if error.get("code")?.as_i64()? != UNKNOWN_OUTPUT_REFERENCES {
+        return None;
+    }
+
+    let inputs = error
+        .get("data")?
+        .get("unknownOutputReferences")?
+        .as_array()?
+        .iter()
+        .map(|reference| {
+            let id = reference.get("transaction")?.get("id")?.as_str()?;
+            let index = reference.get("index")?.as_u64()?;
+            Some(format!("{id}#{index}"))
+        })
+        .collect::<Option<Vec<_>>>()?;
+
+    (!inputs.is_empty()).then(|| inputs.join(", "))
+}
@@ -0,0 +1,150 @@
+//  This Source Code Form is subject to the terms of the Mozilla Public
+//  License, v. 2.0. If a copy of the MPL was not distributed with this
+//  file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use crate::{error::Error, pallas_extra::body_hash};
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use pallas_codec::minicbor as cbor;
+use pallas_crypto::{
+    hash::{Hash, Hasher},
+    key::ed25519::{PublicKey, Signature},
+};
+use pallas_primitives::conway::{MintedTx, VKeyWitness};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+const ARG_TX_FILE: &str = "tx-file";
+
+pub(crate) fn cmd() -> Command {
+    Command::new("verify")
+        .about("Check the signatures of a (possibly partially) witnessed transaction.")
+        .after_help(color_print::cstr!(
+            r#"<underline><bold>Notes:</bold></underline>
+  1. This command doesn't require any network access.
+  2. Each witness is reported as <bold>valid</bold>; <bold>stale</bold> when it doesn't sign the current body (e.g. it was
+     made before the body was edited); or <bold>invalid</bold> when the key or signature are malformed.
+  3. Required signers without any valid witness are reported as missing. Signatories needed for spending
+     key-locked inputs cannot be known offline, and aren't reported.
+
+<underline><bold>Example:</bold></underline>
+  <bold>verify</bold> <bold>--tx-file</bold> vote.signed
+"#
+        ))
+        .arg(
+            Arg::new(ARG_TX_FILE)
+                .long(ARG_TX_FILE)
+                .value_name("FILEPATH")
+                .required(true)
+                .help("A transaction, as a text envelope (e.g. from the cardano-cli) or as hex-encoded CBOR.")
+                .action(ArgAction::Set),
+        )
+}
+
+pub(crate) fn get_arg_tx_file(args: &ArgMatches) -> PathBuf {
+    PathBuf::from(args.get_one::<String>(ARG_TX_FILE).unwrap())
+}
+
+#[derive(Debug)]
+pub(crate) enum WitnessStatus {
+    Valid,
+    Stale,
+    Invalid,
+}
+
+#[derive(Debug)]
+pub(crate) struct Verification {
+    body_hash: Hash<32>,
+    witnesses: Vec<(String, WitnessStatus)>,
+    missing_signers: Vec<Hash<28>>,
+}
+
+impl Verification {
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "bodyHash": self.body_hash.to_string(),
+            "witnesses": self.witnesses.iter().map(|(key, status)| {
+                serde_json::json!({
+                    "vkey": key,
+                    "status": match status {
+                        WitnessStatus::Valid => "valid",
+                        WitnessStatus::Stale => "stale",
+                        WitnessStatus::Invalid => "invalid",
+                    },
+                })
+            }).collect::<Vec<_>>(),
+            "missingSigners": self.missing_signers.iter().map(|h| h.to_string()).collect::<Vec<_>>(),
+        })
+    }
+}
+
+pub(crate) fn verify(tx_file: &Path) -> Result<Verification, Error> {
+    let bytes = read_transaction(tx_file)?;
+
+    let tx: MintedTx = cbor::decode(&bytes)
+        .map_err(|e| Error::MalformedTransaction(tx_file.to_path_buf(), e.to_string()))?;
+
+    let body_hash = body_hash(&tx);
+
+    let mut signed_by = vec![];
+    let witnesses = tx
+        .transaction_witness_set
+        .vkeywitness
+        .iter()
+        .flat_map(|witnesses| witnesses.iter())
+        .map(|VKeyWitness { vkey, signature }| {
+            let status = match (
+                PublicKey::try_from(&vkey[..]),
+                Signature::try_from(&signature[..]),
+            ) {
+                (Ok(public_key), Ok(signature)) => {
+                    if public_key.verify(body_hash, &signature) {
+                        signed_by.push(Hasher::<224>::hash(&vkey[..]));
+                        WitnessStatus::Valid
+                    } else {
+                        WitnessStatus::Stale
+                    }
+                }
+                _ => WitnessStatus::Invalid,
+            };
+            (hex::encode(&vkey[..]), status)
+        })
+        .collect();
+
+    let missing_signers = tx
+        .transaction_body
+        .required_signers
+        .iter()
+        .flat_map(|signers| signers.iter())
+        .filter(|signer| !signed_by.contains(signer))
+        .cloned()
+        .collect();
+
+    Ok(Verification {
+        body_hash,
+        witnesses,
+        missing_signers,
+    })
+}
+
+pub(crate) fn read_transaction(tx_file: &Path) -> Result<Vec<u8>, Error> {
+    let content = fs::read_to_string(tx_file)
+        .map_err(|e| Error::FailedToReadFile(tx_file.to_path_buf(), e))?;
+
+    parse_transaction(&content).map_err(|e| Error::MalformedTransaction(tx_file.to_path_buf(), e))
+}
+
+// Either a text envelope, or the CBOR straight away.
+pub(crate) fn parse_transaction(content: &str) -> Result<Vec<u8>, String> {
+    let cbor_hex = match serde_json::from_str::<serde_json::Value>(content) {
+        Ok(envelope) => envelope
+            .get("cborHex")
+            .and_then(|v| v.as_str())
+            .map(|s| s.to_string())
+            .ok_or_else(|| "missing 'cborHex' in text envelope".to_string())?,
+        Err(_) => content.trim().to_string(),
+    };
+
+    hex::decode(cbor_hex).map_err(|e| e.to_string())
+}
@@ -2,42 +2,136 @@
 //  License, v. 2.0. If a copy of the MPL was not distributed with this
 //  file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
-use crate::{cardano::Cardano, contract::*, pallas_extra::*};
-use clap::{ArgGroup, Command};
+use crate::{
+    backend::Backend,
+    cardano::drep_id,
+    contract::*,
+    error::Error,
+    pallas_extra::*,
+    warnings::{Warning, Warnings},
+};
+use clap::{ArgAction, ArgGroup, Command};
 use pallas_codec::utils::{NonEmptyKeyValuePairs, Nullable, Set};
 use pallas_crypto::hash::Hash;
 use pallas_primitives::conway::{
-    Anchor, GovActionId, Language, PlutusV3Script, PostAlonzoTransactionOutput,
-    PseudoTransactionOutput, TransactionBody, Tx, Vote, Voter, VotingProcedure, WitnessSet,
+    Anchor, ExUnits, GovActionId, PostAlonzoTransactionOutput, PseudoTransactionOutput,
+    TransactionBody, Tx, Vote, Voter, VotingProcedure,
 };
 
 pub(crate) fn cmd() -> Command {
     Command::new("vote")
-        .about("Vote on a governance action.")
+        .about("Vote on one or more governance actions.")
         .after_help(color_print::cstr!(
             r#"<underline><bold>Notes:</bold></underline>
   1. The specified <bold>--delegate</bold> must reflect the signatories for the transaction, but not necessarily ALL delegates.
      Only those authorizing the transaction must be present. And, there must be enough signatories for a quorum.
+  2. When <bold>--contract</bold> is given multiple times, the same vote is cast for each DRep. Signatories must then
+     satisfy the quorum of every contract.
+  3. The transaction expires when the proposal's voting window closes, so that late votes fail rather than go uncounted.
+     Use <bold>--no-auto-ttl</bold> to opt out.
+  4. Votes can be prepared ahead of a proposal reaching the chain with <bold>--allow-unconfirmed-proposal</bold>. Beware that
+     the proposal identifier is then used as-is: a vote on an action that never exists is rejected on submission.
+  5. Casting the very same vote again is refused, unless <bold>--force</bold> is given. Changing a vote already cast
+     requires <bold>--replace-vote</bold>; first-time votes need neither.
+  6. Without <bold>--contract</bold>, the contract is looked up at the address of the given <bold>--validator</bold> (or <bold>--validator-hash</bold>).
+     This fails when there are several contracts at that address; pick one with <bold>--contract</bold> then.
+  7. Instead of <bold>--proposal</bold>, one may give the transaction that submitted it with <bold>--proposal-from-tx</bold>. When that
+     transaction carries several proposals, pick one with <bold>--proposal-index</bold>.
+  8. Several proposals can be voted on at once by repeating <bold>--proposal</bold>. Choices then pair with proposals in
+     the order they're given (e.g. <bold>--yes</bold> <bold>--no</bold> <bold>--yes</bold>), or a single choice applies to all of them.
+  9. Likewise, <bold>--anchor</bold> may be given once per <bold>--proposal</bold>, in the same order. Votes left without an anchor
+     carry none. <bold>--anchor-hash</bold> and <bold>--anchor-file</bold> only work with a single anchor.
 
 <underline><bold>Example:</bold></underline>
   <bold>vote</bold> \
     <bold>--yes</bold> \
     <bold>--proposal</bold> "2ad082a4f85d4a66e8bb240ecd147a8351228ebd0995bef90c4d14f61d4b19cc#0" \
+    <bold>--proposal</bold> "2ad082a4f85d4a66e8bb240ecd147a8351228ebd0995bef90c4d14f61d4b19cc#1" \
     <bold>--anchor</bold> "https://metadata.cardanoapi.io/data/climate" \
     <bold>--delegate</bold> 000000000000000000000000000000000000000000000000000a11ce \
     <bold>--contract</bold> "8d5726c0e7cb207a3f5881d29a7ceba71f578c2165a2261340c242bdba6875dd#0" \
     <bold>--fuel</bold> "ab5334d2db6f7909b511ee9c0f7181c7f4da515ba15f186d95caef0d91ac4a11#0"
 "#              ))
         .arg(super::arg_proposal())
-        .arg(super::arg_anchor())
+        .arg(super::arg_proposal_from_tx())
+        .arg(super::arg_proposal_index())
+        .arg(super::arg_confirm_proposal_hash())
+        .arg(super::flag_strict())
+        .arg(
+            super::arg_anchor()
+                .action(ArgAction::Append)
+                .help("An (optional) URL to an anchor file containing rationale. Use once per --proposal, in the same order, for distinct rationales."),
+        )
+        .arg(super::arg_anchor_hash())
+        .arg(super::arg_anchor_file())
+        .arg(super::flag_insecure())
         .arg(super::flag_yes())
         .arg(super::flag_no())
         .arg(super::flag_abstain())
         .arg(super::arg_delegate())
-        .arg(super::arg_contract(true))
+        .arg(
+            super::arg_contract(false)
+                .action(ArgAction::Append)
+                .help("The UTxO holding the contract's state. Use multiple times to vote as multiple DReps (e.g. while migrating validators)."),
+        )
+        .arg(super::arg_validator())
+        .arg(super::arg_validator_hash())
+        .arg(super::arg_token_prefix())
         .arg(super::arg_fuel())
+        .arg(super::arg_change_address())
+        .arg(super::arg_keep_change_above())
+        .arg(super::arg_donation())
+        .arg(super::flag_collateral_only_ada())
+        .arg(super::flag_no_collateral_return())
+        .arg(super::arg_collateral_percent())
+        .arg(super::flag_no_auto_ttl())
+        .arg(super::flag_allow_unconfirmed_proposal())
+        .arg(super::flag_force())
+        .arg(super::flag_replace_vote())
+        .arg(super::arg_fee_constant())
+        .arg(super::arg_fee_coefficient())
+        .arg(super::arg_price_mem())
+        .arg(super::arg_price_steps())
+        .arg(super::flag_dump_fee_model())
+        .arg(super::arg_max_fee())
+        .arg(super::arg_assume_yes_convergence())
+        .arg(super::arg_redeemer())
+        .arg(super::arg_bundle())
+        .arg(super::arg_plan())
+        .arg(super::arg_debug_attempts())
+        .arg(super::arg_max_build_attempts())
+        .arg(super::arg_expected_signers())
+        .arg(super::flag_double_check_eval())
+        .arg(super::arg_epoch())
+        .arg(super::arg_protocol_params())
+        .arg(super::arg_era())
+        .arg(super::arg_encoding())
+        .arg(super::arg_out())
+        .arg(super::arg_dump_resolved_inputs())
+        .arg(super::arg_resolved_inputs())
+        .arg(super::arg_network())
+        .arg(super::arg_simulate_fee_bump())
+        .arg(super::flag_best_effort())
+        .arg(super::flag_explain())
+        .arg(super::flag_timings())
+        .arg(super::flag_abort_on_warning())
+        .arg(super::flag_verify_on_chain_hash())
+        .arg(super::arg_validity_start())
+        .arg(super::arg_ttl())
+        .arg(super::arg_valid_for())
+        .arg(super::arg_valid_for_epochs())
         .group(ArgGroup::new("vote")
             .args(["yes", "no", "abstain"])
+            .multiple(true)
+            .required(true)
+        )
+        .group(ArgGroup::new("proposal-source")
+            .args(["proposal", "proposal-from-tx"])
+            .multiple(false)
+            .required(true)
+        )
+        .group(ArgGroup::new("source")
+            .args(["contract", "validator", "validator-hash"])
             .multiple(false)
             .required(true)
         )
@@ -45,78 +139,245 @@ pub(crate) fn cmd() -> Command {
 
 #[allow(clippy::too_many_arguments)]
 pub(crate) async fn vote(
-    network: Cardano,
+    network: &impl Backend,
     delegates: Vec<Hash<28>>,
-    choice: Vote,
-    anchor: Option<Anchor>,
-    proposal_id: GovActionId,
-    OutputReference(contract): OutputReference,
-    OutputReference(fuel): OutputReference,
-) -> Tx {
-    let (validator, validator_hash, _) =
-        recover_validator(&network, &contract.transaction_id).await;
-
-    let params = network.protocol_parameters().await;
-
-    let resolved_inputs = network.resolve_many(&[&fuel, &contract]).await;
+    votes: Vec<(GovActionId, Vote)>,
+    anchors: Vec<Anchor>,
+    contracts: Vec<OutputReference>,
+    fuels: Vec<OutputReference>,
+    auto_ttl: bool,
+    allow_unconfirmed_proposal: bool,
+    force: bool,
+    replace_vote: bool,
+    options: &BuildOptions,
+) -> Result<Tx, Error> {
+    let contracts = contracts
+        .into_iter()
+        .map(|OutputReference(contract)| contract)
+        .collect::<Vec<_>>();
+
+    let fuels = fuels
+        .into_iter()
+        .map(|OutputReference(fuel)| fuel)
+        .collect::<Vec<_>>();
+    let fuel = fuels[0].clone();
+
+    if anchors.len() > votes.len() {
+        return Err(Error::AnchorCountMismatch {
+            proposals: votes.len(),
+            anchors: anchors.len(),
+        });
+    }
+
+    for fuel in fuels.iter() {
+        super::ensure_unspent_fuel(network, fuel).await?;
+    }
+
+    let params = network.protocol_parameters().await?;
+
+    let resolved_inputs = network
+        .resolve_many(&fuels.iter().chain(contracts.iter()).collect::<Vec<_>>())
+        .await?;
     let fuel_output = expect_post_alonzo(&resolved_inputs[0].output);
-    let contract_output = expect_post_alonzo(&resolved_inputs[1].output);
+    let change_address = super::change_address(network, options, &fuel_output.address)?;
 
-    let (rules, _) = recover_rules(&network, &validator_hash, &contract_output.value).await;
+    // NOTE: Voters are ordered by script hash in the voting procedures, and so must be their
+    // respective vote redeemers.
+    let mut voters = vec![];
+    for (contract, resolved_contract) in contracts.iter().zip(&resolved_inputs[fuels.len()..]) {
+        let (validator, validator_hash, validator_address) =
+            recover_validator(network, &contract.transaction_id).await?;
+        let contract_output = expect_post_alonzo(&resolved_contract.output);
+        if options.verify_on_chain_hash {
+            ensure_validator_address(contract, &validator_address, &contract_output.address)?;
+        }
+        let (rules, _) = recover_rules(
+            network,
+            &validator_hash,
+            contract,
+            &contract_output.value,
+            &options.token_prefix,
+        )
+        .await?;
+        voters.push((validator_hash, validator, rules));
+    }
+    voters.sort_by_key(|(validator_hash, _, _)| *validator_hash);
+    if let Some(w) = voters.windows(2).find(|w| w[0].0 == w[1].0) {
+        return Err(Error::DuplicateVoter {
+            drep_id: drep_id(&w[0].0),
+        });
+    }
+
+    // NOTE: Re-casting an identical vote merely overwrites it; only fees change hands. Voting
+    // for several DReps (or on several proposals) at once still goes through as long as one of
+    // them has something new to say, which in turn requires all of them to be part of the
+    // transaction.
+    //
+    // Overwriting a *different* vote is just as possible, but rarely accidental-proof; so it
+    // must be asked for explicitly.
+    if !force || !replace_vote {
+        let mut unchanged = 0;
+        for (validator_hash, _, _) in voters.iter() {
+            for (proposal_id, choice) in votes.iter() {
+                match network.drep_vote(validator_hash, proposal_id).await? {
+                    Some(previous) if previous == *choice => {
+                        options.warnings.push(Warning::AlreadyVoted {
+                            drep_id: drep_id(validator_hash),
+                            choice: format!("{choice:?}"),
+                            proposal: format_proposal(proposal_id),
+                        });
+                        unchanged += 1;
+                    }
+                    Some(previous) if !replace_vote => {
+                        return Err(Error::VoteChangeNotConfirmed {
+                            drep_id: drep_id(validator_hash),
+                            proposal: format_proposal(proposal_id),
+                            previous: format!("{previous:?}"),
+                            choice: format!("{choice:?}"),
+                        });
+                    }
+                    // Either a change that was asked for, or a first vote.
+                    Some(_) | None => {}
+                }
+            }
+        }
+        if !force && unchanged == voters.len() * votes.len() {
+            return Err(Error::AlreadyVoted {
+                proposal: votes
+                    .iter()
+                    .map(|(proposal_id, _)| format_proposal(proposal_id))
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            });
+        }
+    }
+
+    super::explain(options, || {
+        serde_json::json!({
+            "authorizedBy": "delegates",
+            "signatories": delegates.iter().map(|h| h.to_string()).collect::<Vec<_>>(),
+            "dreps": voters
+                .iter()
+                .map(|(validator_hash, _, rules)| {
+                    serde_json::json!({
+                        "drepId": drep_id(validator_hash),
+                        "rules": super::explain_rules(rules, &delegates[..]),
+                    })
+                })
+                .collect::<Vec<_>>(),
+            "votes": votes
+                .iter()
+                .enumerate()
+                .map(|(ix, (proposal_id, choice))| {
+                    serde_json::json!({
+                        "proposal": format_proposal(proposal_id),
+                        "choice": format!("{choice:?}"),
+                        "anchor": anchors.get(ix).map(|anchor| anchor.url.clone()),
+                    })
+                })
+                .collect::<Vec<_>>(),
+        })
+    });
+
+    // NOTE: The transaction must go through before the earliest of the voting windows closes.
+    let mut ttl = None;
+    if auto_ttl {
+        for (proposal_id, _) in votes.iter() {
+            let deadline = voting_deadline(
+                network,
+                proposal_id,
+                allow_unconfirmed_proposal,
+                &options.warnings,
+            )
+            .await?;
+            ttl = match (ttl, deadline) {
+                (Some(ttl), Some(deadline)) => Some(u64::min(ttl, deadline)),
+                (ttl, deadline) => ttl.or(deadline),
+            };
+        }
+    }
 
     build_transaction(
         &BuildParams::from(&params),
+        options,
         &resolved_inputs[..],
         |fee, ex_units| {
             let mut redeemers = vec![];
 
-            let inputs = vec![fuel.clone()];
+            let donation = options.donation.map(u64::from).unwrap_or(0);
+
+            let (inputs, fuel_value) =
+                select_fuel(&resolved_inputs[..fuels.len()], fee + donation, &[]);
 
-            let reference_inputs = vec![contract.clone()];
+            let mut reference_inputs = contracts.clone();
+            reference_inputs.sort();
 
-            let outputs = vec![
+            let mut outputs = vec![
                 // Change
                 PostAlonzoTransactionOutput {
-                    address: fuel_output.address.clone(),
-                    value: value_subtract_lovelace(fuel_output.value.clone(), fee)
-                        .expect("not enough fuel"),
+                    address: change_address.clone(),
+                    value: change_value(fuel_value, fee + donation)?,
                     datum_option: None,
                     script_ref: None,
                 },
             ];
 
-            let total_collateral = (fee as f64 * params.collateral_percent).ceil() as u64;
+            let fee = fold_dust_change(
+                &mut outputs,
+                0,
+                fee,
+                params.min_utxo_deposit_coefficient,
+                options.keep_change_above,
+            );
 
-            let collateral_return = PostAlonzoTransactionOutput {
-                address: fuel_output.address.clone(),
-                value: value_subtract_lovelace(fuel_output.value.clone(), total_collateral)
-                    .expect("not enough fuel"),
-                datum_option: None,
-                script_ref: None,
-            };
+            let total_collateral = total_collateral(fee, params.collateral_percent);
 
-            let votes = vec![(
-                Voter::DRepScript(validator_hash),
-                NonEmptyKeyValuePairs::Def(vec![(
-                    proposal_id.clone(),
-                    VotingProcedure {
-                        vote: choice.clone(),
-                        anchor: anchor.clone().map(Nullable::Some).unwrap_or(Nullable::Null),
-                    },
-                )]),
-            )];
-            redeemers.push(Redeemer::vote(0, rules.clone(), ex_units[0]));
+            let collateral_return = collateral_return(&fuel, fuel_output, total_collateral)?;
+
+            // NOTE: All votes of a DRep are cast under the same voter, so a single vote redeemer
+            // (per DRep) covers them all.
+            let mut voting_procedures = vec![];
+            for (ix, (validator_hash, _, rules)) in voters.iter().enumerate() {
+                voting_procedures.push((
+                    Voter::DRepScript(*validator_hash),
+                    NonEmptyKeyValuePairs::Def(
+                        votes
+                            .iter()
+                            .enumerate()
+                            .map(|(ix, (proposal_id, choice))| {
+                                (
+                                    proposal_id.clone(),
+                                    VotingProcedure {
+                                        vote: choice.clone(),
+                                        anchor: anchors.get(ix).cloned().into(),
+                                    },
+                                )
+                            })
+                            .collect(),
+                    ),
+                ));
+                redeemers.push(Redeemer::vote(
+                    ix as u32,
+                    rules.clone(),
+                    ex_units
+                        .get(ix)
+                        .cloned()
+                        .unwrap_or(ExUnits { mem: 0, steps: 0 }),
+                ));
+            }
 
             // ----- Put it all together
-            let redeemers = non_empty_pairs(redeemers).unwrap();
-            Tx {
+            let redeemers = required_pairs("redeemers", options.override_redeemers(redeemers))?;
+            Ok(Tx {
                 transaction_body: TransactionBody {
                     inputs: Set::from(inputs),
                     reference_inputs: non_empty_set(reference_inputs),
                     network_id: Some(from_network(network.network_id())),
                     outputs: into_outputs(outputs),
-                    voting_procedures: non_empty_pairs(votes),
+                    voting_procedures: non_empty_pairs(voting_procedures),
+                    ttl,
                     fee,
+                    donation: options.donation,
                     collateral: non_empty_set(vec![fuel.clone()]),
                     collateral_return: Some(PseudoTransactionOutput::PostAlonzo(collateral_return)),
                     total_collateral: Some(total_collateral),
@@ -125,20 +386,59 @@ pub(crate) async fn vote(
                         script_integrity_hash(
                             Some(&redeemers),
                             None,
-                            &[(Language::PlutusV3, &params.cost_model_v3[..])],
+                            &[(options.era.plutus_language(), &params.cost_model_v3[..])],
                         )
                         .unwrap(),
                     ),
                     ..default_transaction_body()
                 },
-                transaction_witness_set: WitnessSet {
-                    redeemer: Some(redeemers.into()),
-                    plutus_v3_script: non_empty_set(vec![PlutusV3Script(validator.clone())]),
-                    ..default_witness_set()
-                },
+                transaction_witness_set: new_witness_set(
+                    redeemers,
+                    None,
+                    &voters
+                        .iter()
+                        .map(|(_, validator, _)| validator)
+                        .collect::<Vec<_>>(),
+                ),
                 success: true,
                 auxiliary_data: Nullable::Null,
-            }
+            })
         },
     )
 }
+
+// Votes cast after a proposal's window has closed are accepted by the ledger, yet never counted.
+// So we'd rather have the transaction fail (as expired) than succeed uselessly. A proposal that
+// isn't on-chain yet has no known window, so there's nothing to bound the transaction to.
+async fn voting_deadline(
+    network: &impl Backend,
+    proposal_id: &GovActionId,
+    allow_unconfirmed_proposal: bool,
+    warnings: &Warnings,
+) -> Result<Option<u64>, Error> {
+    let id = format_proposal(proposal_id);
+
+    let proposal = match network.proposal(proposal_id).await? {
+        Some(proposal) => proposal,
+        None if allow_unconfirmed_proposal => {
+            warnings.push(Warning::UnconfirmedProposal { proposal: id });
+            return Ok(None);
+        }
+        None => return Err(Error::ProposalNotFound { proposal: id }),
+    };
+
+    let deadline = network.epoch_boundary(proposal.expiration).await?;
+
+    if !proposal.is_active() || network.tip().await?.slot >= deadline {
+        return Err(Error::ProposalNoLongerActive { proposal: id });
+    }
+
+    Ok(Some(deadline))
+}
+
+fn format_proposal(proposal_id: &GovActionId) -> String {
+    format!(
+        "{}#{}",
+        proposal_id.transaction_id, proposal_id.action_index
+    )
+}
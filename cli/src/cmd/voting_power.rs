@@ -0,0 +1,34 @@
+//  This Source Code Form is subject to the terms of the Mozilla Public
+//  License, v. 2.0. If a copy of the MPL was not distributed with this
+//  file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use crate::{cardano::Cardano, error::Error};
+use clap::{ArgGroup, Command};
+use pallas_crypto::hash::Hash;
+
+pub(crate) fn cmd() -> Command {
+    Command::new("voting-power")
+        .about("Show the stake currently delegated to the delegate representative.")
+        .after_help(color_print::cstr!(
+            r#"<underline><bold>Notes:</bold></underline>
+  1. This fails when the delegate representative isn't registered; a registered one may still have no voting power.
+
+<underline><bold>Examples:</bold></underline>
+  <bold>voting-power</bold> \
+    <bold>--validator</bold> $(jq -r ".validators[0].compiledCode" plutus.json)
+
+  <bold>voting-power</bold> \
+    <bold>--validator-hash</bold> 0000000000000000000000000000000000000000000000000000090d
+"#              ))
+        .arg(super::arg_validator())
+        .arg(super::arg_validator_hash())
+        .group(
+            ArgGroup::new("script")
+                .args(["validator", "validator-hash"])
+                .required(true),
+        )
+}
+
+pub(crate) async fn voting_power(network: Cardano, validator_hash: Hash<28>) -> Result<u64, Error> {
+    network.drep_stake(&validator_hash).await
+}
@@ -2,13 +2,27 @@
 //  License, v. 2.0. If a copy of the MPL was not distributed with this
 //  file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
-use crate::{cardano::Cardano, pallas_extra::*};
-use pallas_addresses::ShelleyAddress;
+use crate::{backend::Backend, error::Error, pallas_extra::*};
+use pallas_addresses::{Address, ShelleyAddress};
 use pallas_codec::utils::Bytes;
 use pallas_crypto::hash::{Hash, Hasher};
-use pallas_primitives::conway::{AssetName, Constr, PlutusData, RedeemerTag, Value};
+use pallas_primitives::conway::{
+    AssetName, BigInt, Certificate, Constr, DatumOption, PlutusData, PostAlonzoTransactionOutput,
+    RedeemerTag, TransactionInput, Tx, Value,
+};
 
-pub(crate) fn build_rules(delegates: &[Hash<28>], quorum: usize) -> (PlutusData, AssetName) {
+/// The default prefix of state tokens' asset names. The on-chain validator must agree with any
+/// other.
+pub(crate) const GOVERNANCE_PREFIX: &[u8] = b"gov_";
+
+// Rules are multisig scripts over the delegates: 'AnyOf' (123) for a quorum of one, 'AtLeast' (124)
+// otherwise. The quorum is thereby part of the rules, and of the state token's name; so changing
+// only the quorum still yields a new token.
+pub(crate) fn build_rules(
+    delegates: &[Hash<28>],
+    quorum: usize,
+    prefix: &[u8],
+) -> (PlutusData, AssetName) {
     assert!(
         quorum <= delegates.len(),
         "quorum cannot be larger than number of delegates"
@@ -16,29 +30,138 @@ pub(crate) fn build_rules(delegates: &[Hash<28>], quorum: usize) -> (PlutusData,
 
     assert!(!delegates.is_empty(), "there must be at least one delegate");
 
-    let rules = PlutusData::Constr(Constr {
-        tag: 123,
-        any_constructor: None,
-        fields: vec![PlutusData::Array(
-            delegates
-                .iter()
-                .map(|delegate| {
-                    PlutusData::Constr(Constr {
-                        tag: 121,
-                        any_constructor: None,
-                        fields: vec![PlutusData::BoundedBytes(
-                            delegate.as_slice().to_vec().into(),
-                        )],
-                    })
+    let signatures = PlutusData::Array(
+        delegates
+            .iter()
+            .map(|delegate| {
+                PlutusData::Constr(Constr {
+                    tag: 121,
+                    any_constructor: None,
+                    fields: vec![PlutusData::BoundedBytes(
+                        delegate.as_slice().to_vec().into(),
+                    )],
                 })
-                .collect::<Vec<_>>(),
-        )],
-    });
+            })
+            .collect::<Vec<_>>(),
+    );
+
+    let rules = if quorum == 1 {
+        PlutusData::Constr(Constr {
+            tag: 123,
+            any_constructor: None,
+            fields: vec![signatures],
+        })
+    } else {
+        PlutusData::Constr(Constr {
+            tag: 124,
+            any_constructor: None,
+            fields: vec![
+                PlutusData::BigInt(BigInt::Int((quorum as i64).into())),
+                signatures,
+            ],
+        })
+    };
 
-    let mut asset_name = "gov_".as_bytes().to_vec();
-    asset_name.extend(Hasher::<224>::hash_cbor(&rules).as_slice());
+    let asset_name = rules_asset_name(&rules, prefix);
 
-    (rules, asset_name.into())
+    (rules, asset_name)
+}
+
+// The state token is named after (a hash of) the rules it stands for.
+fn rules_asset_name(rules: &PlutusData, prefix: &[u8]) -> AssetName {
+    let mut asset_name = prefix.to_vec();
+    asset_name.extend(Hasher::<224>::hash_cbor(rules).as_slice());
+    asset_name.into()
+}
+
+/// Delegates and quorum, as defined by some rules.
+#[derive(Debug)]
+pub(crate) struct Rules {
+    pub delegates: Vec<Hash<28>>,
+    pub quorum: usize,
+}
+
+impl Rules {
+    pub fn is_satisfied_by(&self, signatories: &[Hash<28>]) -> bool {
+        self.delegates
+            .iter()
+            .filter(|delegate| signatories.contains(delegate))
+            .count()
+            >= self.quorum
+    }
+
+    /// Human-readable changes from these rules to the given ones; e.g. "quorum 2 → 3".
+    pub fn diff(&self, new: &Rules) -> Vec<String> {
+        let mut changes = vec![];
+        if self.quorum != new.quorum {
+            changes.push(format!("quorum {} → {}", self.quorum, new.quorum));
+        }
+        for delegate in new.delegates.iter() {
+            if !self.delegates.contains(delegate) {
+                changes.push(format!("+ delegate {delegate}"));
+            }
+        }
+        for delegate in self.delegates.iter() {
+            if !new.delegates.contains(delegate) {
+                changes.push(format!("- delegate {delegate}"));
+            }
+        }
+        changes
+    }
+}
+
+// The inverse of 'build_rules', restricted to flat lists of signatures as there's no telling what
+// a nested multisig script would mean in terms of delegates and quorum. Recognizes 'AllOf' (122),
+// 'AnyOf' (123) and 'AtLeast' (124).
+pub(crate) fn parse_rules(rules: &PlutusData) -> Option<Rules> {
+    let signatures = |data: &PlutusData| match data {
+        PlutusData::Array(scripts) => scripts
+            .iter()
+            .map(|script| match script {
+                PlutusData::Constr(Constr {
+                    tag: 121, fields, ..
+                }) => match &fields[..] {
+                    [PlutusData::BoundedBytes(key_hash)] if key_hash.len() == 28 => {
+                        Some(Hash::from(&key_hash[..]))
+                    }
+                    _ => None,
+                },
+                _ => None,
+            })
+            .collect::<Option<Vec<_>>>(),
+        _ => None,
+    };
+
+    match rules {
+        PlutusData::Constr(Constr {
+            tag: 122, fields, ..
+        }) => match &fields[..] {
+            [scripts] => signatures(scripts).map(|delegates| Rules {
+                quorum: delegates.len(),
+                delegates,
+            }),
+            _ => None,
+        },
+        PlutusData::Constr(Constr {
+            tag: 123, fields, ..
+        }) => match &fields[..] {
+            [scripts] => signatures(scripts).map(|delegates| Rules {
+                quorum: 1,
+                delegates,
+            }),
+            _ => None,
+        },
+        PlutusData::Constr(Constr {
+            tag: 124, fields, ..
+        }) => match &fields[..] {
+            [PlutusData::BigInt(BigInt::Int(quorum)), scripts] => {
+                let quorum = usize::try_from(i128::from(*quorum)).ok()?;
+                signatures(scripts).map(|delegates| Rules { delegates, quorum })
+            }
+            _ => None,
+        },
+        _ => None,
+    }
 }
 
 // To avoid re-asking users for the delegates and quorum during vote (which is (1) inconvenient,
@@ -46,47 +169,76 @@ pub(crate) fn build_rules(delegates: &[Hash<28>], quorum: usize) -> (PlutusData,
 // the minting transaction corresponding to the current state token. The token is always minted
 // alongside a DRep registration certificate which defines the new rules as redeemer.
 pub(crate) async fn recover_rules(
-    network: &Cardano,
+    network: &impl Backend,
     validator_hash: &Hash<28>,
+    contract: &TransactionInput,
     contract_value: &Value,
-) -> (PlutusData, AssetName) {
-    let asset_name = find_contract_token(contract_value).expect("no state token in contract utxo?");
+    prefix: &[u8],
+) -> Result<(PlutusData, AssetName), Error> {
+    let asset_name = expect_contract_token(contract, contract_value, validator_hash, prefix)?;
 
-    let minting_txs = network.minting(validator_hash, &asset_name).await;
+    let minting_txs = network.minting(validator_hash, &asset_name).await?;
 
-    let minting_tx = minting_txs.first().unwrap_or_else(|| {
-        panic!(
-            "no minting transaction found for {}",
-            hex::encode(&asset_name[..]),
-        )
-    });
+    // NOTE: A token may have been minted and burned several times over (e.g. when re-delegating
+    // back to former rules). The latest mint is the one that counts.
+    let rules = minting_txs
+        .iter()
+        .rev()
+        .filter(|tx| mints(tx, validator_hash, &asset_name))
+        .find_map(registration_rules)
+        .ok_or_else(|| Error::RulesNotRecoverable {
+            asset_name: hex::encode(&asset_name[..]),
+        })?;
 
-    let rules = if let Some(ref redeemers) = minting_tx.transaction_witness_set.redeemer {
-        redeemers
-            .iter()
-            .find_map(|(key, value)| {
-                if key.tag == RedeemerTag::Cert && value.data != void() {
-                    Some(value.data.clone())
-                } else {
-                    None
-                }
-            })
-            .expect("could not find registration certificate alongside minting transaction?!")
-    } else {
-        unreachable!()
-    };
+    // NOTE: The token's name commits to the rules. Rules that don't hash back to it (e.g. from a
+    // mint carrying other rules) cannot be trusted to authorize anything.
+    let expected = rules_asset_name(&rules, prefix);
+    if expected != asset_name {
+        return Err(Error::RulesIntegrityMismatch {
+            expected: hex::encode(&expected[..]),
+            actual: hex::encode(&asset_name[..]),
+        });
+    }
 
-    (rules, asset_name)
+    Ok((rules, asset_name))
+}
+
+fn mints(tx: &Tx, policy: &Hash<28>, asset_name: &AssetName) -> bool {
+    tx.transaction_body.mint.as_ref().is_some_and(|mint| {
+        mint.iter()
+            .filter(|(policy_id, _)| policy_id == policy)
+            .flat_map(|(_, assets)| assets.iter())
+            .any(|(name, quantity)| name == asset_name && i64::from(quantity) > 0)
+    })
+}
+
+// The rules are the redeemer of the DRep registration certificate.
+fn registration_rules(tx: &Tx) -> Option<PlutusData> {
+    let certificates = tx.transaction_body.certificates.as_ref()?;
+    tx.transaction_witness_set
+        .redeemer
+        .as_ref()?
+        .iter()
+        .find_map(|(key, value)| {
+            let is_registration = key.tag == RedeemerTag::Cert
+                && matches!(
+                    certificates.get(key.index as usize),
+                    Some(Certificate::RegDRepCert(..))
+                );
+            (is_registration && value.data != void()).then(|| value.data.clone())
+        })
 }
 
 pub(crate) async fn recover_validator(
-    network: &Cardano,
+    network: &impl Backend,
     transaction_id: &Hash<32>,
-) -> (Bytes, Hash<28>, ShelleyAddress) {
+) -> Result<(Bytes, Hash<28>, ShelleyAddress), Error> {
     let validator = network
         .transaction_by_hash(&hex::encode(transaction_id))
-        .await
-        .expect("Could not resolve contract UTxO?")
+        .await?
+        .ok_or_else(|| Error::InputNotFound {
+            input: transaction_id.to_string(),
+        })?
         .transaction_witness_set
         .plutus_v3_script
         .expect("No Plutus script found in the provided contract UTxO?")
@@ -98,15 +250,124 @@ pub(crate) async fn recover_validator(
     let (validator_hash, validator_address) =
         from_validator(validator.as_ref(), network.network_id());
 
-    (validator, validator_hash, validator_address)
+    Ok((validator, validator_hash, validator_address))
 }
 
-pub(crate) fn find_contract_token(value: &Value) -> Option<AssetName> {
+// NOTE: Only the validator's own policy counts; other assets held in custody may well share the
+// prefix.
+pub(crate) fn find_contract_token(
+    value: &Value,
+    validator_hash: &Hash<28>,
+    prefix: &[u8],
+) -> Option<AssetName> {
     match value {
         Value::Multiasset(_, ref assets) => assets
-            .first()
-            .and_then(|(_, assets)| assets.first().cloned()),
-        _ => None,
+            .iter()
+            .filter(|(policy, _)| policy == validator_hash)
+            .find_map(|(_, assets)| {
+                assets
+                    .iter()
+                    .find(|(asset_name, _)| asset_name.starts_with(prefix))
+                    .map(|(asset_name, _)| asset_name.clone())
+            }),
+        Value::Coin(_) => None,
+    }
+}
+
+// Like 'find_contract_token', but for when the contract UTxO was provided by users, who may have
+// pointed at the wrong UTxO.
+pub(crate) fn expect_contract_token(
+    contract: &TransactionInput,
+    value: &Value,
+    validator_hash: &Hash<28>,
+    prefix: &[u8],
+) -> Result<AssetName, Error> {
+    find_contract_token(value, validator_hash, prefix).ok_or_else(|| Error::NoStateToken {
+        utxo: OutputReference(contract.clone()).to_string(),
+    })
+}
+
+// The validator is recovered from (or given alongside) the contract, but nothing guarantees it is
+// the one actually locking the contract UTxO; in which case the ledger would reject the
+// transaction. So, when asked to, we catch the mistake before building anything.
+pub(crate) fn ensure_validator_address(
+    contract: &TransactionInput,
+    validator_address: &ShelleyAddress,
+    contract_address: &[u8],
+) -> Result<(), Error> {
+    if validator_address.to_vec() == contract_address {
+        return Ok(());
+    }
+
+    let bech32 = |bytes: &[u8]| {
+        Address::from_bytes(bytes)
+            .ok()
+            .and_then(|address| address.to_bech32().ok())
+            .unwrap_or_else(|| hex::encode(bytes))
+    };
+
+    Err(Error::ValidatorContractMismatch {
+        contract: OutputReference(contract.clone()).to_string(),
+        expected: bech32(&validator_address.to_vec()),
+        actual: bech32(contract_address),
+    })
+}
+
+// Contracts are usually deployed once per validator, so the validator alone is enough to find the
+// contract UTxO. Yet nothing prevents several instances from sharing an address, in which case we
+// refuse to guess.
+pub(crate) async fn discover_contract(
+    network: &impl Backend,
+    validator_hash: Hash<28>,
+    prefix: &[u8],
+) -> Result<OutputReference, Error> {
+    let validator_address = validator_address(validator_hash, network.network_id());
+
+    let state_tokens = network
+        .utxos_at(&validator_address)
+        .await?
+        .into_iter()
+        .filter_map(|utxo| {
+            find_contract_token(
+                &expect_post_alonzo(&utxo.output).value,
+                &validator_hash,
+                prefix,
+            )
+        })
+        .collect::<Vec<_>>();
+
+    // NOTE: The address only tells where tokens sit; the token itself is what identifies a
+    // contract. So look each one up, which also catches a state token held by several outputs.
+    let mut candidates = vec![];
+    for asset_name in state_tokens.iter() {
+        for (input, _) in network.asset_utxos(&validator_hash, asset_name).await? {
+            candidates.push(OutputReference(input));
+        }
+    }
+
+    match candidates.len() {
+        0 => Err(Error::ContractNotFound {
+            address: validator_address.to_bech32().unwrap(),
+        }),
+        1 => Ok(candidates.remove(0)),
+        _ => Err(Error::AmbiguousContract {
+            candidates: candidates.iter().map(|c| c.to_string()).collect(),
+        }),
+    }
+}
+
+// Spending an output locked with a datum hash requires the datum itself in the witness set.
+pub(crate) async fn spent_datums(
+    network: &impl Backend,
+    output: &PostAlonzoTransactionOutput,
+) -> Result<Vec<PlutusData>, Error> {
+    match output.datum_option {
+        Some(DatumOption::Hash(ref hash)) => match network.datum(hash).await? {
+            Some(datum) => Ok(vec![datum]),
+            None => Err(Error::DatumNotFound {
+                hash: hash.to_string(),
+            }),
+        },
+        _ => Ok(vec![]),
     }
-    .map(|pair| pair.0)
 }
@@ -0,0 +1,190 @@
+//  This Source Code Form is subject to the terms of the Mozilla Public
+//  License, v. 2.0. If a copy of the MPL was not distributed with this
+//  file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use crate::cmd::ParseFailure;
+use pallas_addresses::Network;
+use pallas_primitives::conway::{ExUnits, RedeemerTag};
+use std::{io, path::PathBuf};
+
+#[allow(dead_code)]
+#[derive(Debug)]
+pub(crate) enum Error {
+    Parse(ParseFailure),
+    AbortedOnWarnings {
+        warnings: usize,
+    },
+    AlreadyVoted {
+        proposal: String,
+    },
+    AmbiguousAnchorContent {
+        anchors: usize,
+    },
+    AmbiguousContract {
+        candidates: Vec<String>,
+    },
+    AmbiguousProposal {
+        transaction: String,
+        proposals: usize,
+    },
+    AnchorCountMismatch {
+        proposals: usize,
+        anchors: usize,
+    },
+    CollateralWithAssets {
+        input: String,
+    },
+    ContractNotFound {
+        address: String,
+    },
+    DatumNotFound {
+        hash: String,
+    },
+    DidNotConverge {
+        attempts: u8,
+        fee: u64,
+        ex_units: Vec<ExUnits>,
+    },
+    DRepNotFound {
+        drep_id: String,
+    },
+    DuplicateProposal {
+        proposal: String,
+    },
+    DuplicateVoter {
+        drep_id: String,
+    },
+    EmptyRequiredField(&'static str),
+    EmptyValidityInterval {
+        start: u64,
+        ttl: u64,
+    },
+    EpochParametersUnavailable {
+        epoch: u64,
+    },
+    ExecutionBudgetExceeded {
+        tag: RedeemerTag,
+        index: u32,
+        mem: u64,
+        steps: u64,
+    },
+    FailedToFetchAnchor(String, String),
+    FailedToReadFile(PathBuf, io::Error),
+    FailedToWriteFile(PathBuf, io::Error),
+    FeeExceedsCap {
+        fee: u64,
+        cap: u64,
+    },
+    FuelAlreadySpent {
+        input: String,
+    },
+    InputNotFound {
+        input: String,
+    },
+    InsufficientFuel {
+        available: u64,
+        required: u64,
+    },
+    InsufficientFuelAssets {
+        fuel: String,
+        asset: String,
+        available: u64,
+    },
+    InsufficientCollateral {
+        lovelace: u64,
+        required: u64,
+    },
+    InvalidAnchorContent(String, String),
+    LossyConversion(String),
+    MalformedPlan(PathBuf, String),
+    MalformedSigningKey(PathBuf, String),
+    MalformedTransaction(PathBuf, String),
+    MissingAnchorUrl,
+    MissingCredentials(&'static str),
+    MissingResolvedInput {
+        input: String,
+    },
+    NetworkMismatch {
+        expected: String,
+        actual: String,
+    },
+    NoSigners,
+    NoStateToken {
+        utxo: String,
+    },
+    OfflineLookup(&'static str),
+    OutputBelowMinimum {
+        output: String,
+        lovelace: u64,
+        minimum: u64,
+    },
+    PlanMismatch {
+        expected: String,
+        actual: String,
+    },
+    ProviderUnavailable(String),
+    ProposalHashMismatch {
+        proposal: String,
+        confirmation: String,
+    },
+    ProposalNotFound {
+        proposal: String,
+    },
+    ProposalNoLongerActive {
+        proposal: String,
+    },
+    RedeemerIndexOutOfRange {
+        tag: RedeemerTag,
+        index: u32,
+    },
+    RefundBelowMinimum {
+        lovelace: u64,
+        minimum: u64,
+    },
+    RulesIntegrityMismatch {
+        expected: String,
+        actual: String,
+    },
+    RulesNotRecoverable {
+        asset_name: String,
+    },
+    ScriptEvaluationFailed(String),
+    ScriptIntegrityMismatch {
+        expected: String,
+        actual: String,
+    },
+    StakeSourceWithoutStakeKey {
+        utxo: String,
+    },
+    SubmissionRejected(serde_json::Value),
+    UnexpectedNetwork {
+        address: String,
+        expected: Network,
+    },
+    UnrecognizedCredentials(&'static str),
+    UnsuitableCollateral {
+        input: String,
+        reason: String,
+    },
+    ValidatorContractMismatch {
+        contract: String,
+        expected: String,
+        actual: String,
+    },
+    VoteChangeNotConfirmed {
+        drep_id: String,
+        proposal: String,
+        previous: String,
+        choice: String,
+    },
+    VoteCountMismatch {
+        proposals: usize,
+        choices: usize,
+    },
+}
+
+impl From<ParseFailure> for Error {
+    fn from(e: ParseFailure) -> Self {
+        Error::Parse(e)
+    }
+}
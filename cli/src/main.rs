@@ -2,72 +2,479 @@
 //  License, v. 2.0. If a copy of the MPL was not distributed with this
 //  file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
+use backend::Backend;
+use base64::prelude::*;
 use cardano::Cardano;
-use indoc::printdoc;
+use clap::ArgMatches;
+use error::Error;
+use indoc::{formatdoc, printdoc};
 use pallas_codec::minicbor as cbor;
+use pallas_crypto::hash::Hasher;
+use pallas_extra::{payment_key_hash, BuildOptions, Encoding};
 use pallas_primitives::conway::Tx;
+use std::{
+    fs,
+    io::{self, Write},
+    ops::AsyncFn,
+    path::{Path, PathBuf},
+    process,
+};
 
+mod backend;
+mod cache;
 mod cardano;
 mod cmd;
 mod contract;
+mod error;
 mod pallas_extra;
+mod timings;
+mod warnings;
 
+/// Exit code when interrupted, following the shell convention of 128 + SIGINT.
+const EXIT_CANCELLED: i32 = 130;
+
+// NOTE: Dropping the command's future on Ctrl-C cancels whatever request is in flight. There's
+// nothing to roll back: the transaction is only ever emitted once fully built.
 #[tokio::main]
-async fn main() -> Result<(), cmd::ParseFailure> {
+async fn main() -> Result<(), Error> {
+    tokio::select! {
+        result = run() => result,
+        _ = tokio::signal::ctrl_c() => {
+            eprintln!("cancelled by user.");
+            process::exit(EXIT_CANCELLED);
+        }
+    }
+}
+
+async fn run() -> Result<(), Error> {
     match cmd::cli().get_matches().subcommand() {
         Some(("vote", args)) => {
-            let network = Cardano::new();
+            let mut options = cmd::get_build_options(args)?;
+            let network = connect(args, &options)?;
+            options.ttl = cmd::get_arg_ttl(args, &network).await?;
+            options.slots = network.slots();
 
             let delegates = cmd::get_arg_delegates(args)?;
-            let choice = cmd::get_arg_vote(args);
-            let anchor = cmd::get_arg_anchor(args).await;
-            let proposal = cmd::get_arg_proposal(args)?;
-            let contract = cmd::get_arg_contract(args)?.unwrap();
-            let fuel = cmd::get_arg_fuel(args)?;
+            let anchors = cmd::get_arg_anchors(args, &options.warnings).await?;
+            let proposals = cmd::get_arg_proposals_or_lookup(args, &network).await?;
+            let votes = cmd::get_arg_votes(args, proposals)?;
+            let contracts = match cmd::get_arg_contracts(args)? {
+                contracts if contracts.is_empty() => {
+                    vec![
+                        cmd::get_arg_contract_or_discover(args, &network, &options.token_prefix)
+                            .await?,
+                    ]
+                }
+                contracts => contracts,
+            };
+            let fuels = cmd::get_arg_fuels(args)?;
+            let auto_ttl = cmd::get_auto_ttl(args);
+            let allow_unconfirmed_proposal = cmd::get_allow_unconfirmed_proposal(args);
+            let force = cmd::get_force(args);
+            let replace_vote = cmd::get_replace_vote(args);
 
-            report(cmd::vote(network, delegates, choice, anchor, proposal, contract, fuel).await)
+            build_and_report(args, &options, network, async |network| {
+                cmd::vote(
+                    network,
+                    delegates.clone(),
+                    votes.clone(),
+                    anchors.clone(),
+                    contracts.clone(),
+                    fuels.clone(),
+                    auto_ttl,
+                    allow_unconfirmed_proposal,
+                    force,
+                    replace_vote,
+                    &options,
+                )
+                .await
+            })
+            .await
         }
 
         Some(("delegate", args)) => {
-            let network = Cardano::new();
+            let mut options = cmd::get_build_options(args)?;
+            let network = connect(args, &options)?;
+            options.ttl = cmd::get_arg_ttl(args, &network).await?;
+            options.slots = network.slots();
 
             let contract = cmd::get_arg_contract(args)?;
             let administrators = cmd::get_arg_administrators(args)?;
+            cmd::ensure_signers(
+                &administrators,
+                cmd::get_allow_no_signers(args),
+                &options.warnings,
+            )?;
             let delegates = cmd::get_arg_delegates(args)?;
-            let quorum = cmd::get_arg_quorum(args)?.unwrap_or(delegates.len());
-            let fuel = cmd::get_arg_fuel(args)?;
+            let quorum = cmd::get_arg_quorum(args, delegates.len())?;
+            let anchor = cmd::get_arg_anchor(args, &options.warnings).await?;
+            let fuels = cmd::get_arg_fuels(args)?;
+            let assets = cmd::get_arg_assets(args)?;
+            let datum = cmd::get_arg_datum(args);
 
-            report(if let Some(contract) = contract {
-                cmd::redelegate(network, delegates, quorum, administrators, contract, fuel).await
+            if let Some(contract) = contract {
+                build_and_report(args, &options, network, async |network| {
+                    cmd::redelegate(
+                        network,
+                        delegates.clone(),
+                        quorum,
+                        administrators.clone(),
+                        anchor.clone(),
+                        contract.clone(),
+                        fuels.clone(),
+                        assets.clone(),
+                        datum,
+                        &options,
+                    )
+                    .await
+                })
+                .await
             } else {
                 let validator = cmd::get_arg_validator(args)?.unwrap();
-                cmd::delegate(network, delegates, quorum, administrators, validator, fuel).await
-            })
+                let with_stake = cmd::get_arg_with_stake(args)?;
+                build_and_report(args, &options, network, async |network| {
+                    cmd::delegate(
+                        network,
+                        delegates.clone(),
+                        quorum,
+                        administrators.clone(),
+                        anchor.clone(),
+                        validator.clone(),
+                        fuels.clone(),
+                        assets.clone(),
+                        datum,
+                        with_stake.clone(),
+                        &options,
+                    )
+                    .await
+                })
+                .await
+            }
         }
 
         Some(("revoke", args)) => {
-            let network = Cardano::new();
+            let mut options = cmd::get_build_options(args)?;
+            let network = connect(args, &options)?;
+            options.ttl = cmd::get_arg_ttl(args, &network).await?;
+            options.slots = network.slots();
 
-            let contract = cmd::get_arg_contract(args)?.unwrap();
-            let fuel = cmd::get_arg_fuel(args)?;
+            let contract =
+                cmd::get_arg_contract_or_discover(args, &network, &options.token_prefix).await?;
+            let fuels = cmd::get_arg_fuels(args)?;
             let administrators = cmd::get_arg_administrators(args)?;
-            report(cmd::revoke(network, administrators, contract, fuel).await)
+            let refund_address = cmd::get_arg_refund_address(args)?;
+            build_and_report(args, &options, network, async |network| {
+                cmd::revoke(
+                    network,
+                    administrators.clone(),
+                    contract.clone(),
+                    fuels.clone(),
+                    refund_address.clone(),
+                    &options,
+                )
+                .await
+            })
+            .await
+        }
+
+        Some(("params", args)) => {
+            let params = cmd::params(Cardano::new()?.with_epoch(cmd::get_arg_epoch(args)?)).await?;
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&params.to_json()).unwrap()
+            );
+            Ok(())
+        }
+
+        Some(("health", _)) => {
+            let health = cmd::health(Cardano::new()?).await?;
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&health.to_json()).unwrap()
+            );
+            Ok(())
+        }
+
+        Some(("asset-name", args)) => {
+            let delegates = cmd::get_arg_delegates(args)?;
+            let quorum = cmd::get_arg_quorum(args, delegates.len())?;
+            let prefix = cmd::get_arg_token_prefix(args)?;
+            let asset_name = cmd::asset_name(delegates, quorum, &prefix);
+            printdoc! {
+                r#"{{
+                  "assetName": "{}",
+                  "prefix": "{}"
+                }}"#,
+                hex::encode(&asset_name[..]),
+                String::from_utf8_lossy(&prefix),
+            };
+            Ok(())
+        }
+
+        Some(("voting-power", args)) => {
+            let validator_hash = cmd::get_arg_validator_hash(args)?;
+            let lovelace = cmd::voting_power(Cardano::new()?, validator_hash).await?;
+            let voting_power = serde_json::json!({
+                "lovelace": lovelace,
+                "ada": format!("{}.{:06}", lovelace / 1_000_000, lovelace % 1_000_000),
+            });
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&serde_json::json!({
+                    "drepId": cardano::drep_id(&validator_hash),
+                    "votingPower": voting_power,
+                }))
+                .unwrap()
+            );
+            Ok(())
+        }
+
+        Some(("key-hash", args)) => {
+            let vkey = cmd::get_arg_vkey(args)?;
+            println!("{}", cmd::key_hash(vkey));
+            Ok(())
+        }
+
+        Some(("generate-key", args)) => {
+            let (signing_key_file, verification_key_file) = cmd::get_arg_key_files(args);
+            let key_hash = cmd::generate_key(&signing_key_file, &verification_key_file)?;
+            println!("{key_hash}");
+            Ok(())
+        }
+
+        Some(("sign", args)) => {
+            let tx = cmd::get_arg_tx_or_plan(args)?;
+            let signing_keys = cmd::get_arg_signing_keys(args)?;
+            let envelope = cmd::sign(&tx, &signing_keys)?;
+            match cmd::get_arg_out(args) {
+                Some(path) => write_atomically(&path, envelope.as_bytes())
+                    .map_err(|e| Error::FailedToWriteFile(path, e))?,
+                None => io::stdout().write_all(envelope.as_bytes()).unwrap(),
+            }
+            Ok(())
+        }
+
+        Some(("submit", args)) => {
+            let tx = cmd::get_arg_tx(args)?;
+            let transaction_id = cmd::submit(&tx, &cmd::get_arg_ogmios_url(args)).await?;
+            println!("{transaction_id}");
+            Ok(())
+        }
+
+        Some(("inspect", args)) => {
+            let tx = cmd::get_arg_tx(args)?;
+            let cost_model = cmd::get_arg_cost_model(args).await?;
+            let inspection = cmd::inspect(&tx, cost_model.as_deref())?;
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&inspection.to_json()).unwrap()
+            );
+            inspection.ensure_script_integrity()
+        }
+
+        Some(("verify", args)) => {
+            let verification = cmd::verify(&cmd::get_arg_tx_file(args))?;
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&verification.to_json()).unwrap()
+            );
+            Ok(())
+        }
+
+        Some(("simulate-quorum", args)) => {
+            let contract = cmd::get_arg_contract(args)?.unwrap();
+            let delegates = cmd::get_arg_delegates(args)?;
+            let prefix = cmd::get_arg_token_prefix(args)?;
+            let simulation =
+                cmd::simulate_quorum(Cardano::new()?, delegates, contract, &prefix).await?;
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&simulation.to_json()).unwrap()
+            );
+            Ok(())
+        }
+
+        Some(("rules-schema", _)) => {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&cmd::rules_schema()).unwrap()
+            );
+            Ok(())
+        }
+
+        Some(("completions", args)) => {
+            cmd::completions(cmd::get_arg_shell(args));
+            Ok(())
         }
 
         _ => unreachable!(),
     }
 }
 
-fn report<E>(tx: Tx) -> Result<(), E> {
+fn connect(args: &ArgMatches, options: &BuildOptions) -> Result<Cardano, Error> {
+    let resolved_inputs = cmd::get_arg_resolved_inputs(args)?;
+
+    // NOTE: With everything given ahead of time, there's no need for credentials.
+    let network = match cmd::get_arg_network(args) {
+        Some(network) if cmd::has_arg_protocol_params(args) && resolved_inputs.is_some() => {
+            Cardano::offline(network)
+        }
+        Some(network) => Cardano::new_on(network)?,
+        None => Cardano::new()?,
+    };
+
+    let protocol_parameters = cmd::get_arg_protocol_params(args, Some(network.network_name()))?;
+
+    Ok(network
+        .with_timings(options.timings.clone())
+        .with_warnings(options.warnings.clone())
+        .with_epoch(cmd::get_arg_epoch(args)?)
+        .with_overrides(cmd::get_protocol_parameters_overrides(args)?)
+        .with_protocol_parameters(protocol_parameters)
+        .with_resolved_inputs(resolved_inputs))
+}
+
+// Either report the transaction as-is, or rebuild it under increasingly expensive protocol
+// parameters and report how its fee evolves; so that fuel can be sized with some headroom.
+async fn build_and_report<F>(
+    args: &ArgMatches,
+    options: &BuildOptions,
+    network: Cardano,
+    build: F,
+) -> Result<(), Error>
+where
+    F: AsyncFn(&Cardano) -> Result<Tx, Error>,
+{
+    let bumps = cmd::get_arg_simulate_fee_bump(args)?;
+    if bumps.is_empty() {
+        return report(build(&network).await?, options, &network).await;
+    }
+
+    let mut baseline = None;
+    println!("{:>6}  {:>12}  {:>10}", "bump", "fee", "delta");
+    for bump in bumps {
+        let tx = build(&connect(args, options)?.with_fee_bump(bump)).await?;
+        let fee = tx.transaction_body.fee;
+        let baseline = *baseline.get_or_insert(fee);
+        println!(
+            "{:>5}%  {:>12}  {:>+10}",
+            bump,
+            fee,
+            fee as i64 - baseline as i64
+        );
+    }
+
+    options.warnings.render();
+    options.timings.summary();
+    Ok(())
+}
+
+async fn report(tx: Tx, options: &BuildOptions, network: &impl Backend) -> Result<(), Error> {
+    if options.abort_on_warning && !options.warnings.is_empty() {
+        options.warnings.render();
+        return Err(Error::AbortedOnWarnings {
+            warnings: options.warnings.len(),
+        });
+    }
+
     let mut buf = Vec::new();
-    cbor::encode(tx, &mut buf).unwrap();
-    printdoc! {
-        r#"{{
-          "type": "Unwitnessed Tx ConwayEra",
-          "description": "Ledger Cddl Format",
-          "cborHex": "{}"
-        }}"#,
-        hex::encode(&buf),
+    cbor::encode(&tx, &mut buf).unwrap();
+
+    let bytes = match options.encoding {
+        Encoding::TextEnvelope => formatdoc! {
+            r#"{{
+              "type": "{}",
+              "description": "{}",
+              "cborHex": "{}"
+            }}
+            "#,
+            options.era.envelope_type(),
+            options.era.envelope_description(),
+            hex::encode(&buf),
+        }
+        .into_bytes(),
+        Encoding::CborHex => format!("{}\n", hex::encode(&buf)).into_bytes(),
+        Encoding::Base64 => format!("{}\n", BASE64_STANDARD.encode(&buf)).into_bytes(),
+        Encoding::Raw => buf,
+        Encoding::WitnessRequest => format!(
+            "{}\n",
+            serde_json::to_string_pretty(&witness_request(&tx, network).await?).unwrap()
+        )
+        .into_bytes(),
     };
+
+    match options.out {
+        Some(ref path) => write_atomically(path, &bytes)
+            .map_err(|e| Error::FailedToWriteFile(path.to_path_buf(), e))?,
+        None => io::stdout().write_all(&bytes).unwrap(),
+    }
+
+    options.warnings.render();
+    options.timings.summary();
     Ok(())
 }
+
+// Just enough for offline signers to produce their witness: what to sign, and who must sign it.
+// Besides required signers, owners of spent key-locked inputs (e.g. the fuel) must sign too; and
+// so must those of collateral inputs, in case scripts fail.
+async fn witness_request(tx: &Tx, network: &impl Backend) -> Result<serde_json::Value, Error> {
+    let mut body = Vec::new();
+    cbor::encode(&tx.transaction_body, &mut body).unwrap();
+
+    let mut input_signers = vec![];
+    for input in tx.transaction_body.inputs.iter().chain(
+        tx.transaction_body
+            .collateral
+            .iter()
+            .flat_map(|collateral| collateral.iter()),
+    ) {
+        // NOTE: Inputs were all resolved while building, so this doesn't hit the network again.
+        // Script-locked inputs have no key to sign with; unresolved ones might, so they're an error.
+        let resolved = network
+            .resolve(input)
+            .await
+            .ok_or_else(|| Error::InputNotFound {
+                input: format!("{}#{}", input.transaction_id, input.index),
+            })?;
+        if let Some(key_hash) = payment_key_hash(&resolved.output) {
+            if !input_signers.contains(&key_hash) {
+                input_signers.push(key_hash);
+            }
+        }
+    }
+
+    Ok(serde_json::json!({
+        "bodyHash": Hasher::<256>::hash(&body).to_string(),
+        "requiredSigners": tx
+            .transaction_body
+            .required_signers
+            .iter()
+            .flat_map(|signers| signers.iter())
+            .map(|signer| signer.to_string())
+            .collect::<Vec<_>>(),
+        "inputSigners": input_signers
+            .iter()
+            .map(|signer| signer.to_string())
+            .collect::<Vec<_>>(),
+    }))
+}
+
+// A transaction file is either fully there or not at all; so that whatever watches for it (e.g. a
+// signing script) never picks up a truncated one.
+fn write_atomically(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    if let Some(parent) = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+    {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+
+    fs::write(&tmp, bytes)?;
+    fs::rename(&tmp, path).inspect_err(|_| {
+        let _ = fs::remove_file(&tmp);
+    })
+}
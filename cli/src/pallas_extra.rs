@@ -2,28 +2,227 @@
 //  License, v. 2.0. If a copy of the MPL was not distributed with this
 //  file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
-use pallas_addresses::{Network, ShelleyAddress, ShelleyDelegationPart, ShelleyPaymentPart};
+use crate::{
+    cache, cmd,
+    error::Error,
+    timings::Timings,
+    warnings::{Warning, Warnings},
+};
+use indoc::eprintdoc;
+use pallas_addresses::{
+    Address, Network, ShelleyAddress, ShelleyDelegationPart, ShelleyPaymentPart,
+};
 use pallas_codec::{
     minicbor as cbor,
-    utils::{NonEmptyKeyValuePairs, NonEmptySet, Set},
+    utils::{Bytes, CborWrap, NonEmptyKeyValuePairs, NonEmptySet, PositiveCoin, Set},
 };
 use pallas_crypto::hash::{Hash, Hasher};
 use pallas_primitives::conway::{
-    AssetName, Constr, ExUnits, Language, Multiasset, NetworkId, PlutusData,
-    PostAlonzoTransactionOutput, PseudoTransactionOutput, RedeemerTag, RedeemersKey,
-    RedeemersValue, TransactionBody, TransactionInput, TransactionOutput, Tx, Value, WitnessSet,
+    AssetName, Constr, DatumOption, ExUnits, Language, MintedTx, Multiasset, NetworkId, PlutusData,
+    PlutusV3Script, PostAlonzoTransactionOutput, PseudoTransactionOutput, RedeemerTag,
+    RedeemersKey, RedeemersValue, TransactionBody, TransactionInput, TransactionOutput, Tx, Value,
+    WitnessSet,
+};
+use std::{
+    cell::RefCell,
+    cmp::Ordering,
+    collections::BTreeMap,
+    fmt, fs,
+    path::{Path, PathBuf},
+    rc::Rc,
+    str::FromStr,
+    time::Instant,
 };
-use std::{cmp::Ordering, str::FromStr};
 use uplc::tx::{eval_phase_two, ResolvedInput, SlotConfig};
 
+/// When slots start, and how long they last; for validity intervals to translate into POSIX times
+/// in script contexts. Defaults to mainnet.
+#[derive(Debug, Clone, Copy)]
+pub struct Slots {
+    pub zero_time: u64,
+    pub zero_slot: u64,
+    pub slot_length: u32,
+}
+
+impl Default for Slots {
+    fn default() -> Self {
+        let SlotConfig {
+            zero_time,
+            zero_slot,
+            slot_length,
+        } = SlotConfig::default();
+        Slots {
+            zero_time,
+            zero_slot,
+            slot_length,
+        }
+    }
+}
+
+impl From<Slots> for SlotConfig {
+    fn from(slots: Slots) -> SlotConfig {
+        SlotConfig {
+            zero_time: slots.zero_time,
+            zero_slot: slots.zero_slot,
+            slot_length: slots.slot_length,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct BuildParams {
     pub fee_constant: u64,
     pub fee_coefficient: u64,
     pub price_mem: f64,
     pub price_steps: f64,
+    pub min_utxo_deposit_coefficient: u64,
+}
+
+/// Knobs tweaking how `build_transaction` goes about finding a fixed point, independently of the
+/// protocol parameters; and what commands report along the way.
+#[derive(Debug, Default, Clone)]
+pub struct BuildOptions {
+    pub timings: Timings,
+    pub warnings: Warnings,
+    pub abort_on_warning: bool,
+    pub best_effort: bool,
+    pub explain: bool,
+    pub bundle: Option<PathBuf>,
+    pub plan: Option<PathBuf>,
+    /// What 'explain' came up with, kept around for the plan.
+    pub explanation: Rc<RefCell<Option<serde_json::Value>>>,
+    pub convergence_cache: Option<PathBuf>,
+    pub era: Era,
+    pub debug_attempts: Option<PathBuf>,
+    pub dump_resolved_inputs: Option<PathBuf>,
+    pub redeemer_overrides: Vec<RedeemerOverride>,
+    pub verify_on_chain_hash: bool,
+    pub encoding: Encoding,
+    pub out: Option<PathBuf>,
+    pub keep_change_above: u64,
+    pub donation: Option<PositiveCoin>,
+    pub dump_fee_model: bool,
+    pub collateral_only_ada: bool,
+    pub no_collateral_return: bool,
+    pub token_prefix: Vec<u8>,
+    pub validity_start: Option<u64>,
+    pub ttl: Option<u64>,
+    pub slots: Slots,
+    pub double_check_eval: bool,
+    pub strict: bool,
+    pub max_fee: Option<u64>,
+    pub max_attempts: u8,
+    pub change_address: Option<Address>,
+    pub expected_signers: Option<usize>,
+}
+
+/// The ledger era transactions are built for. There's only one for now, but this is where the
+/// next one plugs in: envelope and script language are all derived from it.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Era {
+    #[default]
+    Conway,
+}
+
+impl Era {
+    pub fn envelope_type(&self) -> &'static str {
+        match self {
+            Era::Conway => "Unwitnessed Tx ConwayEra",
+        }
+    }
+
+    pub fn signed_envelope_type(&self) -> &'static str {
+        match self {
+            Era::Conway => "Signed Tx ConwayEra",
+        }
+    }
+
+    pub fn envelope_description(&self) -> &'static str {
+        match self {
+            Era::Conway => "Ledger Cddl Format",
+        }
+    }
+
+    pub fn plutus_language(&self) -> Language {
+        match self {
+            Era::Conway => Language::PlutusV3,
+        }
+    }
+}
+
+/// Whether, and how, a contract output carries its rules as datum: not at all, inline, or as a hash
+/// with the datum itself in the witness set.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum DatumMode {
+    #[default]
+    None,
+    Inline,
+    Hash,
 }
 
+impl DatumMode {
+    pub fn datum_option(&self, datum: &PlutusData) -> Option<DatumOption> {
+        match self {
+            DatumMode::None => None,
+            DatumMode::Inline => Some(DatumOption::Data(CborWrap(datum.clone()))),
+            DatumMode::Hash => Some(DatumOption::Hash(datum_hash(datum))),
+        }
+    }
+
+    /// Datums to be witnessed alongside the output.
+    pub fn witnesses(&self, datum: &PlutusData) -> Vec<PlutusData> {
+        match self {
+            DatumMode::Hash => vec![datum.clone()],
+            DatumMode::None | DatumMode::Inline => vec![],
+        }
+    }
+}
+
+pub fn datum_hash(datum: &PlutusData) -> Hash<32> {
+    let mut buffer = Vec::new();
+    cbor::encode(datum, &mut buffer).unwrap();
+    Hasher::<256>::hash(&buffer)
+}
+
+/// How the final transaction is handed over to whatever signs it next. Signing tools don't all
+/// agree on a format: some want the text-envelope, others the bare CBOR.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    #[default]
+    TextEnvelope,
+    CborHex,
+    Base64,
+    Raw,
+    WitnessRequest,
+}
+
+/// User-supplied data replacing whatever a command would otherwise use as redeemer at the given
+/// purpose and index; for validators expecting something else than the assumed shapes.
+#[derive(Debug, Clone)]
+pub struct RedeemerOverride {
+    pub tag: RedeemerTag,
+    pub index: u32,
+    pub data: PlutusData,
+}
+
+impl BuildOptions {
+    pub fn override_redeemers(
+        &self,
+        mut redeemers: Vec<(RedeemersKey, RedeemersValue)>,
+    ) -> Vec<(RedeemersKey, RedeemersValue)> {
+        for RedeemerOverride { tag, index, data } in self.redeemer_overrides.iter() {
+            if let Some((_, value)) = redeemers
+                .iter_mut()
+                .find(|(key, _)| key.tag == *tag && key.index == *index)
+            {
+                value.data = data.clone();
+            }
+        }
+        redeemers
+    }
+}
+
+#[derive(Clone)]
 pub struct OutputReference(pub TransactionInput);
 
 impl FromStr for OutputReference {
@@ -47,6 +246,12 @@ impl FromStr for OutputReference {
     }
 }
 
+impl fmt::Display for OutputReference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}#{}", self.0.transaction_id, self.0.index)
+    }
+}
+
 pub struct Redeemer {}
 
 impl Redeemer {
@@ -142,6 +347,19 @@ where
     }
 }
 
+// Like 'non_empty_pairs', for fields that the transaction cannot do without (e.g. redeemers of a
+// script-locked transaction).
+pub fn required_pairs<K, V>(
+    field: &'static str,
+    pairs: Vec<(K, V)>,
+) -> Result<NonEmptyKeyValuePairs<K, V>, Error>
+where
+    V: Clone,
+    K: Clone,
+{
+    non_empty_pairs(pairs).ok_or(Error::EmptyRequiredField(field))
+}
+
 pub fn into_outputs(outputs: Vec<PostAlonzoTransactionOutput>) -> Vec<TransactionOutput> {
     outputs
         .into_iter()
@@ -152,28 +370,36 @@ pub fn into_outputs(outputs: Vec<PostAlonzoTransactionOutput>) -> Vec<Transactio
 pub fn singleton_assets<T: Clone>(
     validator_hash: Hash<28>,
     assets: &[(AssetName, T)],
-) -> Multiasset<T> {
-    NonEmptyKeyValuePairs::Def(vec![(
+) -> Result<Multiasset<T>, Error> {
+    Ok(NonEmptyKeyValuePairs::Def(vec![(
         validator_hash,
-        NonEmptyKeyValuePairs::Def(assets.to_vec()),
-    )])
+        required_pairs("assets", assets.to_vec())?,
+    )]))
 }
 
 pub fn from_validator(validator: &[u8], network_id: Network) -> (Hash<28>, ShelleyAddress) {
     let validator_hash = Hasher::<224>::hash_tagged(validator, 3);
-    let validator_address = ShelleyAddress::new(
+    (
+        validator_hash,
+        validator_address(validator_hash, network_id),
+    )
+}
+
+pub fn validator_address(validator_hash: Hash<28>, network_id: Network) -> ShelleyAddress {
+    ShelleyAddress::new(
         network_id,
         ShelleyPaymentPart::script_hash(validator_hash),
         ShelleyDelegationPart::script_hash(validator_hash),
-    );
-
-    (validator_hash, validator_address)
+    )
 }
 
+// Native assets are carried over as-is. Spending the lovelace exactly is fine: an empty pure-ada
+// change is then dropped by 'fold_dust_change', whereas one still holding assets is caught by the
+// minimum value check.
 pub fn value_subtract_lovelace(value: Value, lovelace: u64) -> Option<Value> {
     match value {
-        Value::Coin(total) if total > lovelace => Some(Value::Coin(total - lovelace)),
-        Value::Multiasset(total, assets) if total > lovelace => {
+        Value::Coin(total) if total >= lovelace => Some(Value::Coin(total - lovelace)),
+        Value::Multiasset(total, assets) if total >= lovelace => {
             Some(Value::Multiasset(total - lovelace, assets))
         }
         _ => None,
@@ -187,6 +413,189 @@ pub fn value_add_lovelace(value: Value, lovelace: u64) -> Value {
     }
 }
 
+/// Some quantity of a native asset, as given on the command-line: `{policy_id}.{asset_name}:{quantity}`
+/// where both the policy id and the asset name are hex-encoded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AssetQuantity {
+    pub policy: Hash<28>,
+    pub name: AssetName,
+    pub quantity: u64,
+}
+
+impl FromStr for AssetQuantity {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let malformed = || {
+            "malformed asset: expected a hex-encoded policy id and asset name separated by '.', followed by ':' and a quantity".to_string()
+        };
+        let (unit, quantity) = s.rsplit_once(':').ok_or_else(malformed)?;
+        let (policy, name) = unit.split_once('.').ok_or_else(malformed)?;
+        let policy: Hash<28> = policy
+            .parse()
+            .map_err(|e| format!("failed to decode policy id from hex: {e:?}"))?;
+        let name = hex::decode(name)
+            .map_err(|e| format!("failed to decode asset name from hex: {e:?}"))?;
+        if name.len() > 32 {
+            return Err(format!("asset name too long: {} bytes", name.len()));
+        }
+        let quantity: u64 = quantity
+            .parse()
+            .map_err(|e| format!("failed to decode quantity: {e:?}"))?;
+        if quantity == 0 {
+            return Err("asset quantity must be strictly positive".to_string());
+        }
+        Ok(AssetQuantity {
+            policy,
+            name: name.into(),
+            quantity,
+        })
+    }
+}
+
+impl fmt::Display for AssetQuantity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}.{}:{}",
+            self.policy,
+            hex::encode(&self.name[..]),
+            self.quantity
+        )
+    }
+}
+
+// Native assets are much easier to add or take from as nested maps than as the ledger's
+// non-empty pairs.
+fn assets_of(value: &Value) -> BTreeMap<Hash<28>, BTreeMap<AssetName, u64>> {
+    let mut assets = BTreeMap::new();
+    if let Value::Multiasset(_, multiasset) = value {
+        for (policy, tokens) in multiasset.iter() {
+            let policy_assets: &mut BTreeMap<AssetName, u64> = assets.entry(*policy).or_default();
+            for (name, quantity) in tokens.iter() {
+                *policy_assets.entry(name.clone()).or_default() += u64::from(quantity);
+            }
+        }
+    }
+    assets
+}
+
+fn from_assets(lovelace: u64, assets: BTreeMap<Hash<28>, BTreeMap<AssetName, u64>>) -> Value {
+    let multiasset = assets
+        .into_iter()
+        .filter_map(|(policy, tokens)| {
+            let tokens = tokens
+                .into_iter()
+                .filter_map(|(name, quantity)| {
+                    PositiveCoin::try_from(quantity).ok().map(|q| (name, q))
+                })
+                .collect::<Vec<_>>();
+            non_empty_pairs(tokens).map(|tokens| (policy, tokens))
+        })
+        .collect::<Vec<_>>();
+
+    match non_empty_pairs(multiasset) {
+        Some(multiasset) => Value::Multiasset(lovelace, multiasset),
+        None => Value::Coin(lovelace),
+    }
+}
+
+pub fn quantity_of(value: &Value, policy: &Hash<28>, name: &AssetName) -> u64 {
+    assets_of(value)
+        .get(policy)
+        .and_then(|tokens| tokens.get(name))
+        .copied()
+        .unwrap_or(0)
+}
+
+// Every native asset held in a value, but the given one; typically, a contract's state token.
+pub fn assets_except(value: &Value, policy: &Hash<28>, name: &AssetName) -> Vec<AssetQuantity> {
+    assets_of(value)
+        .into_iter()
+        .flat_map(|(p, tokens)| {
+            tokens.into_iter().map(move |(n, quantity)| AssetQuantity {
+                policy: p,
+                name: n,
+                quantity,
+            })
+        })
+        .filter(|asset| !(asset.policy == *policy && asset.name == *name))
+        .collect()
+}
+
+pub fn value_add_assets(value: Value, assets: &[AssetQuantity]) -> Value {
+    let mut total = assets_of(&value);
+    for asset in assets {
+        *total
+            .entry(asset.policy)
+            .or_default()
+            .entry(asset.name.clone())
+            .or_default() += asset.quantity;
+    }
+    from_assets(lovelace_of(&value), total)
+}
+
+pub fn value_subtract_assets(value: Value, assets: &[AssetQuantity]) -> Option<Value> {
+    let mut total = assets_of(&value);
+    for asset in assets {
+        let available = total
+            .get_mut(&asset.policy)
+            .and_then(|tokens| tokens.get_mut(&asset.name))?;
+        *available = available.checked_sub(asset.quantity)?;
+    }
+    Some(from_assets(lovelace_of(&value), total))
+}
+
+pub fn add_values(left: Value, right: &Value) -> Value {
+    let mut total = assets_of(&left);
+    for (policy, tokens) in assets_of(right) {
+        for (name, quantity) in tokens {
+            *total.entry(policy).or_default().entry(name).or_default() += quantity;
+        }
+    }
+    from_assets(lovelace_of(&left) + lovelace_of(right), total)
+}
+
+// Fuel is picked greedily: the first one always (it also serves as collateral), then the largest
+// ones first, until there's enough lovelace and enough of each asset to cover for the costs. When
+// there's not enough, everything is picked and the shortage surfaces when computing the change.
+pub fn select_fuel(
+    fuels: &[ResolvedInput],
+    lovelace: u64,
+    assets: &[AssetQuantity],
+) -> (Vec<TransactionInput>, Value) {
+    let mut candidates = fuels.iter().skip(1).collect::<Vec<_>>();
+    candidates.sort_by_key(|fuel| {
+        std::cmp::Reverse(lovelace_of(&expect_post_alonzo(&fuel.output).value))
+    });
+
+    let mut selected = vec![fuels[0].input.clone()];
+    let mut total = expect_post_alonzo(&fuels[0].output).value.clone();
+    for fuel in candidates {
+        let value = &expect_post_alonzo(&fuel.output).value;
+        let needs_lovelace = lovelace_of(&total) < lovelace;
+        let needs_assets = assets.iter().any(|asset| {
+            quantity_of(&total, &asset.policy, &asset.name) < asset.quantity
+                && quantity_of(value, &asset.policy, &asset.name) > 0
+        });
+        if needs_lovelace || needs_assets {
+            selected.push(fuel.input.clone());
+            total = add_values(total, value);
+        }
+    }
+
+    selected.sort();
+    (selected, total)
+}
+
+// What's left of the fuel once the given lovelace are paid out of it.
+pub fn change_value(fuel_value: Value, lovelace: u64) -> Result<Value, Error> {
+    let available = lovelace_of(&fuel_value);
+    value_subtract_lovelace(fuel_value, lovelace).ok_or(Error::InsufficientFuel {
+        available,
+        required: lovelace,
+    })
+}
+
 pub fn lovelace_of(value: &Value) -> u64 {
     match value {
         Value::Coin(lovelace) | Value::Multiasset(lovelace, _) => *lovelace,
@@ -197,26 +606,186 @@ pub fn new_min_value_output<F>(per_byte: u64, build: F) -> PostAlonzoTransaction
 where
     F: Fn(u64) -> PostAlonzoTransactionOutput,
 {
-    let value = build(1);
+    // NOTE: 4 extra bytes for actual final lovelace value. Technically, the final value could
+    // need 8 more additional bytes if the resulting value was larger than 4_294_967_295 lovelaces,
+    // which would realistically never be the case.
+    build(min_lovelace(per_byte, &build(1)) + 4 * per_byte)
+}
+
+// The minimum lovelace value an output must hold, as per the ledger rules: 160 bytes of overhead
+// plus the output's serialised size.
+pub fn min_lovelace(per_byte: u64, output: &PostAlonzoTransactionOutput) -> u64 {
     let mut buffer: Vec<u8> = Vec::new();
-    cbor::encode(&value, &mut buffer).unwrap();
-    // NOTE: 160 overhead as per the spec + 4 bytes for actual final lovelace value.
-    // Technically, the final value could need 8 more additional bytes if the resulting
-    // value was larger than 4_294_967_295 lovelaces, which would realistically never be
-    // the case.
-    build((buffer.len() as u64 + 164) * per_byte)
+    cbor::encode(output, &mut buffer).unwrap();
+    (buffer.len() as u64 + 160) * per_byte
+}
+
+// Change made of lovelace only, and that is either below the minimum value or below what the user
+// cares to keep, is better given away as extra fee than left behind as dust. Returns the fee that
+// the transaction must then declare.
+pub fn fold_dust_change(
+    outputs: &mut Vec<PostAlonzoTransactionOutput>,
+    change_ix: usize,
+    fee: u64,
+    per_byte: u64,
+    keep_change_above: u64,
+) -> u64 {
+    match outputs[change_ix].value {
+        Value::Coin(lovelace)
+            if lovelace < keep_change_above
+                || lovelace < min_lovelace(per_byte, &outputs[change_ix]) =>
+        {
+            outputs.remove(change_ix);
+            fee + lovelace
+        }
+        _ => fee,
+    }
 }
 
-pub fn total_execution_cost(params: &BuildParams, redeemers: &[ExUnits]) -> u64 {
-    redeemers.iter().fold(0, |acc, ex_units| {
+// Token-bearing collateral is fine since Babbage, as long as tokens go back in the collateral return.
+// Yet, some wallets and tools still choke on it, so this is a check users may opt into.
+fn validate_pure_ada_collateral(tx: &Tx, resolved_inputs: &[ResolvedInput]) -> Result<(), Error> {
+    for input in tx
+        .transaction_body
+        .collateral
+        .iter()
+        .flat_map(|xs| xs.iter())
+    {
+        let holds_assets = resolved_inputs
+            .iter()
+            .find(|r| &r.input == input)
+            .is_some_and(|r| matches!(expect_post_alonzo(&r.output).value, Value::Multiasset(..)));
+        if holds_assets {
+            return Err(Error::CollateralWithAssets {
+                input: OutputReference(input.clone()).to_string(),
+            });
+        }
+    }
+    Ok(())
+}
+
+// Every output (including the collateral return) must hold at least its minimum value. Builders
+// only size the outputs they create from scratch, whereas change outputs simply get what's left.
+pub fn validate_min_values(params: &BuildParams, tx: &Tx) -> Result<(), Error> {
+    let body = &tx.transaction_body;
+
+    let outputs = body
+        .outputs
+        .iter()
+        .enumerate()
+        .map(|(ix, output)| (format!("output #{ix}"), output))
+        .chain(
+            body.collateral_return
+                .iter()
+                .map(|output| ("collateral return".to_string(), output)),
+        );
+
+    for (output, value) in outputs {
+        let value = expect_post_alonzo(value);
+        let minimum = min_lovelace(params.min_utxo_deposit_coefficient, value);
+        let lovelace = lovelace_of(&value.value);
+        if lovelace < minimum {
+            return Err(Error::OutputBelowMinimum {
+                output,
+                lovelace,
+                minimum,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+// Collateral is a percentage of the fee, rounded up. Computed on integers, so that the percentage
+// (a float for convenience) doesn't introduce rounding errors of its own.
+pub fn total_collateral(fee: u64, collateral_percent: f64) -> u64 {
+    let percent = (collateral_percent * 1e2).round() as u64;
+    (fee * percent).div_ceil(100)
+}
+
+pub fn total_execution_cost(
+    params: &BuildParams,
+    redeemers: &[ExUnits],
+    strict: bool,
+) -> Result<u64, Error> {
+    if strict {
+        return exact_execution_cost(params, redeemers);
+    }
+
+    Ok(redeemers.iter().fold(0, |acc, ex_units| {
         acc + ((params.price_mem * ex_units.mem as f64).ceil() as u64)
             + ((params.price_steps * ex_units.steps as f64).ceil() as u64)
-    })
+    }))
+}
+
+// Prices are decimal fractions on-chain (e.g. 577/10000), which floats only approximate. So, like
+// the ledger, price the total execution units at once, on exact ratios, and round up only at the end.
+fn exact_execution_cost(params: &BuildParams, redeemers: &[ExUnits]) -> Result<u64, Error> {
+    let lossy = || {
+        Error::LossyConversion(format!(
+            "execution cost (price_mem={}, price_steps={})",
+            params.price_mem, params.price_steps
+        ))
+    };
+
+    let (mem_num, mem_den) = decimal_ratio(params.price_mem).ok_or_else(lossy)?;
+    let (steps_num, steps_den) = decimal_ratio(params.price_steps).ok_or_else(lossy)?;
+
+    let mem = redeemers
+        .iter()
+        .map(|ex_units| ex_units.mem as u128)
+        .sum::<u128>();
+    let steps = redeemers
+        .iter()
+        .map(|ex_units| ex_units.steps as u128)
+        .sum::<u128>();
+
+    let cost = || {
+        let num = mem
+            .checked_mul(mem_num)?
+            .checked_mul(steps_den)?
+            .checked_add(steps.checked_mul(steps_num)?.checked_mul(mem_den)?)?;
+        let den = mem_den.checked_mul(steps_den)?;
+        u64::try_from(num.div_ceil(den)).ok()
+    };
+
+    cost().ok_or_else(lossy)
+}
+
+// The shortest decimal that reads back as the given float, as a ratio (e.g. 0.0577 -> 577/10000).
+fn decimal_ratio(x: f64) -> Option<(u128, u128)> {
+    if !x.is_finite() || x < 0.0 {
+        return None;
+    }
+    let repr = x.to_string();
+    let (integral, fractional) = repr.split_once('.').unwrap_or((&repr, ""));
+    let den = 10u128.checked_pow(fractional.len() as u32)?;
+    let num = format!("{integral}{fractional}").parse::<u128>().ok()?;
+    Some((num, den))
 }
 
 pub fn script_integrity_hash(
     redeemers: Option<&NonEmptyKeyValuePairs<RedeemersKey, RedeemersValue>>,
-    datums: Option<&NonEmptyKeyValuePairs<Hash<32>, PlutusData>>,
+    datums: Option<&NonEmptySet<PlutusData>>,
+    language_views: &[(Language, &[i64])],
+) -> Option<Hash<32>> {
+    // NOTE: Datums are hashed as they appear in the witness set, hence the shared type.
+    raw_script_integrity_hash(
+        redeemers
+            .map(|redeemers| cbor::to_vec(redeemers).unwrap())
+            .as_deref(),
+        datums
+            .map(|datums| cbor::to_vec(datums).unwrap())
+            .as_deref(),
+        language_views,
+    )
+}
+
+// Like 'script_integrity_hash', but over redeemers and datums already serialised; e.g. as found in
+// an existing transaction, whose bytes may not survive a re-encoding.
+pub fn raw_script_integrity_hash(
+    redeemers: Option<&[u8]>,
+    datums: Option<&[u8]>,
     language_views: &[(Language, &[i64])],
 ) -> Option<Hash<32>> {
     if redeemers.is_none() && language_views.is_empty() && datums.is_none() {
@@ -225,11 +794,11 @@ pub fn script_integrity_hash(
 
     let mut preimage: Vec<u8> = Vec::new();
     if let Some(redeemers) = redeemers {
-        cbor::encode(redeemers, &mut preimage).unwrap();
+        preimage.extend(redeemers);
     }
 
     if let Some(datums) = datums {
-        cbor::encode(datums, &mut preimage).unwrap();
+        preimage.extend(datums);
     }
 
     // NOTE: This doesn't work for PlutusV1, but I don't care.
@@ -291,13 +860,84 @@ pub fn default_witness_set() -> WitnessSet {
     }
 }
 
+// Witness set carrying the given redeemers, datums and Plutus V3 validators. The same validator may
+// be given more than once (e.g. when it's both minting and spending), but only witnessed once.
+//
+// NOTE: Datums must be the very same given to 'script_integrity_hash', or the ledger will reject
+// the transaction.
+pub fn new_witness_set(
+    redeemers: NonEmptyKeyValuePairs<RedeemersKey, RedeemersValue>,
+    datums: Option<NonEmptySet<PlutusData>>,
+    validators: &[&Bytes],
+) -> WitnessSet {
+    let mut scripts: Vec<PlutusV3Script> = vec![];
+    for validator in validators {
+        let script = PlutusV3Script((*validator).clone());
+        if !scripts.contains(&script) {
+            scripts.push(script);
+        }
+    }
+
+    WitnessSet {
+        redeemer: Some(redeemers.into()),
+        plutus_data: datums,
+        plutus_v3_script: non_empty_set(scripts),
+        ..default_witness_set()
+    }
+}
+
 // Build a transaction by repeatedly executing some building logic with different fee and execution
 // units settings. Stops when a fixed point is reached. The final transaction has corresponding
 // fees and execution units.
-pub fn build_transaction<F>(params: &BuildParams, resolved_inputs: &[ResolvedInput], with: F) -> Tx
+pub fn build_transaction<F>(
+    params: &BuildParams,
+    options: &BuildOptions,
+    resolved_inputs: &[ResolvedInput],
+    with: F,
+) -> Result<Tx, Error>
 where
-    F: Fn(u64, &[ExUnits]) -> Tx,
+    F: Fn(u64, &[ExUnits]) -> Result<Tx, Error>,
 {
+    let with = |fee, ex_units: &[ExUnits]| {
+        let tx = with(fee, ex_units).map(without_needless_collateral)?;
+        let tx = with_ttl(tx, options.ttl);
+        let tx = with_validity_start(tx, options.validity_start)?;
+        if options.no_collateral_return {
+            without_collateral_return(tx, resolved_inputs)
+        } else {
+            Ok(tx)
+        }
+    };
+
+    let finish = |tx: Tx| -> Result<Tx, Error> {
+        if let Some(cap) = options.max_fee {
+            let fee = tx.transaction_body.fee;
+            if fee > cap {
+                return Err(Error::FeeExceedsCap { fee, cap });
+            }
+        }
+        validate_min_values(params, &tx)?;
+        if options.double_check_eval && !resolved_inputs.is_empty() {
+            let started = Instant::now();
+            double_check_evaluation(&tx, resolved_inputs, options.slots)?;
+            options.timings.record("evaluation (double-check)", started);
+        }
+        if let Some(ref path) = options.dump_resolved_inputs {
+            fs::write(
+                path,
+                serde_json::to_string_pretty(&resolved_inputs_to_json(resolved_inputs)).unwrap(),
+            )
+            .map_err(|e| Error::FailedToWriteFile(path.to_path_buf(), e))?;
+        }
+        if let Some(ref path) = options.bundle {
+            write_bundle(path, options.era, &tx, resolved_inputs)?;
+        }
+        if let Some(ref path) = options.plan {
+            write_plan(path, params, options, &tx, resolved_inputs)?;
+        }
+        Ok(tx)
+    };
+
     let empty_ex_units = || {
         vec![
             ExUnits { mem: 0, steps: 0 },
@@ -311,79 +951,547 @@ where
     let mut ex_units = empty_ex_units();
 
     let mut tx;
-    let mut attempts = 0;
+    let mut attempts: u8 = 0;
+    let mut fingerprint = None;
     loop {
-        tx = with(fee, &ex_units[..]);
+        tx = with(fee, &ex_units[..])?;
+
+        validate_redeemers(&tx)?;
+
+        if attempts == 0 && options.collateral_only_ada {
+            validate_pure_ada_collateral(&tx, resolved_inputs)?;
+        }
+
+        if attempts == 0 {
+            let redeemers = tx.transaction_witness_set.redeemer.as_ref();
+            for RedeemerOverride { tag, index, .. } in options.redeemer_overrides.iter() {
+                if !redeemers.is_some_and(|redeemers| {
+                    redeemers
+                        .iter()
+                        .any(|(key, _)| key.tag == *tag && key.index == *index)
+                }) {
+                    return Err(Error::RedeemerIndexOutOfRange {
+                        tag: *tag,
+                        index: *index,
+                    });
+                }
+            }
+        }
 
         // Convert to minted_tx...
         let mut serialized_tx = Vec::new();
         cbor::encode(&tx, &mut serialized_tx).unwrap();
 
+        if let Some(ref dir) = options.debug_attempts {
+            dump_attempt(dir, attempts, &serialized_tx, fee, &ex_units[..])?;
+        }
+
+        if let (Some(path), 0) = (&options.convergence_cache, attempts) {
+            let digest = cache::fingerprint(
+                params,
+                resolved_inputs,
+                options.expected_signers,
+                &serialized_tx,
+            );
+            if let Some((fee, ex_units)) = cache::lookup(path, &digest) {
+                return finish(with(fee, &ex_units[..])?);
+            }
+            fingerprint = Some(digest);
+        }
+
         let mut calculated_ex_units = if resolved_inputs.is_empty() {
             empty_ex_units()
         } else {
             // Compute execution units
+            let started = Instant::now();
             let minted_tx = cbor::decode(&serialized_tx).unwrap();
-            eval_phase_two(
+            let ex_units = eval_phase_two(
                 &minted_tx,
                 resolved_inputs,
                 None,
                 None,
-                &SlotConfig::default(),
+                &options.slots.into(),
                 false,
                 |_| (),
             )
-            .expect("script evaluation failed")
+            // NOTE: The evaluation error carries the offending redeemer pointer (e.g. Vote[0]).
+            .map_err(|e| Error::ScriptEvaluationFailed(e.to_string()))?
             .into_iter()
             .map(|r| r.ex_units)
-            .collect::<Vec<_>>()
+            .collect::<Vec<_>>();
+            options
+                .timings
+                .record(format!("evaluation (attempt {})", attempts + 1), started);
+            ex_units
         };
 
         calculated_ex_units.extend(empty_ex_units());
 
         attempts += 1;
 
-        let estimated_fee = {
-            // NOTE: This is a best effort to estimate the number of signatories since signatures
-            // will add an overhead to the fee. Yet, if inputs are locked by native scripts each
-            // requiring multiple signatories, this will unfortunately fall short.
-            //
-            // For similar reasons, it will also over-estimate fees by a small margin for every
-            // script-locked inputs that do not require signatories.
-            //
-            // This is however *acceptable* in our context.
-            let num_signatories = tx.transaction_body.inputs.len()
+        // NOTE: This is a best effort to estimate the number of signatories since signatures
+        // will add an overhead to the fee. Yet, if inputs are locked by native scripts each
+        // requiring multiple signatories, this will unfortunately fall short.
+        //
+        // For similar reasons, it will also over-estimate fees by a small margin for every
+        // script-locked inputs that do not require signatories.
+        //
+        // This is however *acceptable* in our context. And when it isn't, the exact count can be
+        // given instead; which then supersedes the required signers as well.
+        let num_signatories = options.expected_signers.unwrap_or_else(|| {
+            tx.transaction_body.inputs.len()
                 + tx.transaction_body
                     .required_signers
                     .as_ref()
                     .map(|xs| xs.len())
-                    .unwrap_or(0);
+                    .unwrap_or(0)
+        });
 
-            params.fee_constant
-                + params.fee_coefficient
-                    * (5 + ex_units.len() * 16 + num_signatories * 102 + serialized_tx.len()) as u64
-                + total_execution_cost(params, &ex_units)
-        };
+        let execution_cost = total_execution_cost(params, &ex_units, options.strict)?;
+
+        let estimated_fee = params.fee_constant
+            + params.fee_coefficient
+                * (5 + ex_units.len() * 16 + num_signatories * 102 + serialized_tx.len()) as u64
+            + execution_cost;
 
         // Check if we've reached a fixed point, or start over.
         if fee >= estimated_fee
             && calculated_ex_units
                 .iter()
-                .zip(ex_units)
-                .all(|(l, r)| l.eq(&r))
+                .zip(ex_units.iter())
+                .all(|(l, r)| l.eq(r))
         {
+            if let (Some(path), Some(digest)) = (&options.convergence_cache, fingerprint) {
+                cache::store(path, &digest, fee, &ex_units[..]);
+            }
+            if options.dump_fee_model {
+                eprintdoc! {
+                    "
+                    fee model:
+                      fee = fee_constant + fee_coefficient * (5 + ex_units * 16 + signatories * 102 + tx_bytes) + execution_cost
+                          = {} + {} * (5 + {} * 16 + {} * 102 + {}) + {}
+                          = {} lovelace (declared: {} lovelace)
+                    ",
+                    params.fee_constant,
+                    params.fee_coefficient,
+                    ex_units.len(),
+                    num_signatories,
+                    serialized_tx.len(),
+                    execution_cost,
+                    estimated_fee,
+                    fee,
+                };
+            }
             break;
-        } else if attempts >= 3 {
-            panic!("failed to build transaction: did not converge after three attempts.");
+        } else if attempts >= options.max_attempts {
+            if options.best_effort {
+                // NOTE: Fees only ever go up across attempts, so the highest estimate paired with
+                // the latest execution units is our best shot at a valid transaction.
+                let fee = fee.max(estimated_fee);
+                options.warnings.push(Warning::BestEffort { attempts, fee });
+                tx = with(fee, &calculated_ex_units[..])?;
+                break;
+            }
+            let redeemers = tx
+                .transaction_witness_set
+                .redeemer
+                .as_ref()
+                .map(|redeemers| redeemers.iter().count())
+                .unwrap_or_default();
+            calculated_ex_units.truncate(redeemers);
+            return Err(Error::DidNotConverge {
+                attempts,
+                fee: estimated_fee,
+                ex_units: calculated_ex_units,
+            });
         } else {
             ex_units = calculated_ex_units;
             fee = estimated_fee;
         }
     }
 
+    finish(tx)
+}
+
+// Execution units are computed on the previous attempt's body; which is almost always identical to
+// the emitted one, but not quite when the convergence was cut short (cache, best effort). So this
+// evaluates the emitted bytes one last time, and requires every declared budget to still fit.
+fn double_check_evaluation(
+    tx: &Tx,
+    resolved_inputs: &[ResolvedInput],
+    slots: Slots,
+) -> Result<(), Error> {
+    let mut serialized_tx = Vec::new();
+    cbor::encode(tx, &mut serialized_tx).unwrap();
+    let minted_tx = cbor::decode(&serialized_tx).unwrap();
+
+    let evaluated = eval_phase_two(
+        &minted_tx,
+        resolved_inputs,
+        None,
+        None,
+        &slots.into(),
+        false,
+        |_| (),
+    )
+    .map_err(|e| Error::ScriptEvaluationFailed(e.to_string()))?;
+
+    let declared = tx
+        .transaction_witness_set
+        .redeemer
+        .iter()
+        .flat_map(|redeemers| redeemers.iter())
+        .collect::<Vec<_>>();
+
+    for redeemer in evaluated {
+        let fits = declared.iter().any(|(key, value)| {
+            key.tag == redeemer.tag
+                && key.index == redeemer.index
+                && redeemer.ex_units.mem <= value.ex_units.mem
+                && redeemer.ex_units.steps <= value.ex_units.steps
+        });
+        if !fits {
+            return Err(Error::ExecutionBudgetExceeded {
+                tag: redeemer.tag,
+                index: redeemer.index,
+                mem: redeemer.ex_units.mem,
+                steps: redeemer.ex_units.steps,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+fn cbor_hex<T: cbor::Encode<()>>(data: &T) -> String {
+    let mut buffer = Vec::new();
+    cbor::encode(data, &mut buffer).unwrap();
+    hex::encode(buffer)
+}
+
+// Signers (e.g. hardware wallets) can only show meaningful information about what they sign when
+// they know what's being spent. So the bundle carries the transaction together with every input it
+// resolves (spent or referenced), in a single self-contained JSON file.
+fn write_bundle(
+    path: &Path,
+    era: Era,
+    tx: &Tx,
+    resolved_inputs: &[ResolvedInput],
+) -> Result<(), Error> {
+    let bundle = serde_json::json!({
+        "transaction": {
+            "type": era.envelope_type(),
+            "description": era.envelope_description(),
+            "cborHex": cbor_hex(tx),
+        },
+        "resolvedInputs": resolved_inputs_to_json(resolved_inputs),
+    });
+
+    fs::write(path, serde_json::to_string_pretty(&bundle).unwrap())
+        .map_err(|e| Error::FailedToWriteFile(path.to_path_buf(), e))
+}
+
+// Everything a reviewer needs to approve a transaction before it gets signed: what it does, what it
+// costs, what it spends, under which rules, and whatever looked off along the way.
+fn write_plan(
+    path: &Path,
+    params: &BuildParams,
+    options: &BuildOptions,
+    tx: &Tx,
+    resolved_inputs: &[ResolvedInput],
+) -> Result<(), Error> {
+    let body = &tx.transaction_body;
+
+    let mut serialized_tx = Vec::new();
+    cbor::encode(tx, &mut serialized_tx).unwrap();
+
+    let mut serialized_body = Vec::new();
+    cbor::encode(body, &mut serialized_body).unwrap();
+
+    let ex_units = tx
+        .transaction_witness_set
+        .redeemer
+        .iter()
+        .flat_map(|redeemers| redeemers.iter())
+        .map(|(_, value)| value.ex_units)
+        .collect::<Vec<_>>();
+
+    // NOTE: The very same summary 'inspect' gives, so reviewers can check one against the other.
+    let summary = cmd::inspect(&serialized_tx, None)?.to_json();
+
+    let plan = serde_json::json!({
+        "transaction": {
+            "type": options.era.envelope_type(),
+            "description": options.era.envelope_description(),
+            "cborHex": hex::encode(&serialized_tx),
+            "bodyHash": Hasher::<256>::hash(&serialized_body).to_string(),
+            "summary": summary,
+        },
+        "fee": {
+            "declared": body.fee,
+            "size": serialized_tx.len(),
+            "feeConstant": params.fee_constant,
+            "feeCoefficient": params.fee_coefficient,
+            "executionCost": total_execution_cost(params, &ex_units, options.strict)?,
+        },
+        "resolvedInputs": resolved_inputs_to_json(resolved_inputs),
+        "explanation": options.explanation.borrow().clone(),
+        "warnings": options.warnings.to_json(),
+    });
+
+    fs::write(path, serde_json::to_string_pretty(&plan).unwrap())
+        .map_err(|e| Error::FailedToWriteFile(path.to_path_buf(), e))
+}
+
+pub fn resolved_inputs_to_json(resolved_inputs: &[ResolvedInput]) -> serde_json::Value {
+    resolved_inputs
+        .iter()
+        .map(|ResolvedInput { input, output }| {
+            serde_json::json!({
+                "input": OutputReference(input.clone()).to_string(),
+                "output": {
+                    "cborHex": cbor_hex(output),
+                },
+            })
+        })
+        .collect()
+}
+
+pub fn resolved_inputs_from_json(json: &serde_json::Value) -> Result<Vec<ResolvedInput>, String> {
+    json.as_array()
+        .ok_or_else(|| "expected an array of resolved inputs".to_string())?
+        .iter()
+        .map(|resolved_input| {
+            let OutputReference(input) = resolved_input
+                .get("input")
+                .and_then(|input| input.as_str())
+                .ok_or_else(|| "missing 'input' in resolved input".to_string())?
+                .parse()?;
+
+            let output = resolved_input
+                .get("output")
+                .and_then(|output| output.get("cborHex"))
+                .and_then(|cbor_hex| cbor_hex.as_str())
+                .ok_or_else(|| "missing 'output.cborHex' in resolved input".to_string())?;
+
+            let output = hex::decode(output).map_err(|e| e.to_string())?;
+
+            Ok(ResolvedInput {
+                input,
+                output: cbor::decode(&output).map_err(|e| e.to_string())?,
+            })
+        })
+        .collect()
+}
+
+// Resolved inputs as a CBOR list of (input, output) pairs.
+pub fn resolved_inputs_from_cbor(bytes: &[u8]) -> Result<Vec<ResolvedInput>, String> {
+    cbor::decode::<Vec<(TransactionInput, TransactionOutput)>>(bytes)
+        .map(|pairs| {
+            pairs
+                .into_iter()
+                .map(|(input, output)| ResolvedInput { input, output })
+                .collect()
+        })
+        .map_err(|e| e.to_string())
+}
+
+// The transaction id, which signatures are also over. It is computed from the body's original
+// bytes, which may not survive a re-encoding (e.g. indefinite lists, or sets without their tag).
+pub fn body_hash(tx: &MintedTx) -> Hash<32> {
+    Hasher::<256>::hash(tx.transaction_body.raw_cbor())
+}
+
+// The original bytes of a witness set's field (e.g. 4 for Plutus data), which Pallas doesn't keep
+// around for every field; and re-encoding may not reproduce them (e.g. with or without set tag).
+pub fn raw_witness_set_field(witness_set: &[u8], key: u64) -> Option<&[u8]> {
+    let mut decoder = cbor::Decoder::new(witness_set);
+    let len = decoder.map().ok()?;
+    let mut ix = 0;
+    while len.is_none_or(|len| ix < len) {
+        if len.is_none() && decoder.datatype().ok()? == cbor::data::Type::Break {
+            break;
+        }
+        let field = decoder.u64().ok()?;
+        let start = decoder.position();
+        decoder.skip().ok()?;
+        if field == key {
+            return Some(&witness_set[start..decoder.position()]);
+        }
+        ix += 1;
+    }
+    None
+}
+
+// Keep a copy of each intermediate transaction, alongside the fee and execution units it was built
+// with; so that consecutive attempts can be compared when the loop fails to converge.
+fn dump_attempt(
+    dir: &Path,
+    attempt: u8,
+    serialized_tx: &[u8],
+    fee: u64,
+    ex_units: &[ExUnits],
+) -> Result<(), Error> {
+    fs::create_dir_all(dir).map_err(|e| Error::FailedToWriteFile(dir.to_path_buf(), e))?;
+
+    let cbor_file = dir.join(format!("attempt-{attempt}.cbor"));
+    fs::write(&cbor_file, serialized_tx).map_err(|e| Error::FailedToWriteFile(cbor_file, e))?;
+
+    let json_file = dir.join(format!("attempt-{attempt}.json"));
+    let settings = serde_json::json!({
+        "fee": fee,
+        "exUnits": ex_units
+            .iter()
+            .map(|ExUnits { mem, steps }| serde_json::json!({ "mem": mem, "steps": steps }))
+            .collect::<Vec<_>>(),
+    });
+    fs::write(&json_file, serde_json::to_string_pretty(&settings).unwrap())
+        .map_err(|e| Error::FailedToWriteFile(json_file, e))
+}
+
+// Collateral only ever covers for failing scripts. Without any, it's at best useless, and at worst
+// forbidden by the ledger (e.g. a collateral return without any redeemer).
+fn without_needless_collateral(mut tx: Tx) -> Tx {
+    if tx.transaction_witness_set.redeemer.is_none() {
+        tx.transaction_body.collateral = None;
+        tx.transaction_body.collateral_return = None;
+        tx.transaction_body.total_collateral = None;
+    }
     tx
 }
 
+// Like the lower bound, the upper one is set on every attempt. It only ever tightens whatever bound
+// the command itself may have set (e.g. the end of a proposal's voting window).
+fn with_ttl(mut tx: Tx, ttl: Option<u64>) -> Tx {
+    if let Some(ttl) = ttl {
+        let current = tx.transaction_body.ttl;
+        tx.transaction_body.ttl = Some(current.map_or(ttl, |current| current.min(ttl)));
+    }
+    tx
+}
+
+// The lower bound is part of the script context and of the serialized body alike, so it must be set
+// on every attempt; not merely on the final transaction.
+fn with_validity_start(mut tx: Tx, validity_start: Option<u64>) -> Result<Tx, Error> {
+    let Some(start) = validity_start else {
+        return Ok(tx);
+    };
+
+    if let Some(ttl) = tx.transaction_body.ttl {
+        if start >= ttl {
+            return Err(Error::EmptyValidityInterval { start, ttl });
+        }
+    }
+
+    tx.transaction_body.validity_interval_start = Some(start);
+    Ok(tx)
+}
+
+// The fuel doubles as collateral, which the ledger only accepts from key-locked outputs holding
+// enough lovelace to cover the total collateral. Checked here, rather than left to the ledger, so
+// the fuel can be swapped before anything gets signed.
+pub fn collateral_return(
+    collateral: &TransactionInput,
+    output: &PostAlonzoTransactionOutput,
+    total_collateral: u64,
+) -> Result<PostAlonzoTransactionOutput, Error> {
+    let unsuitable = |reason: String| Error::UnsuitableCollateral {
+        input: OutputReference(collateral.clone()).to_string(),
+        reason,
+    };
+
+    if let Ok(Address::Shelley(address)) = Address::from_bytes(&output.address[..]) {
+        if address.payment().is_script() {
+            return Err(unsuitable("locked by a script".to_string()));
+        }
+    }
+
+    let value =
+        value_subtract_lovelace(output.value.clone(), total_collateral).ok_or_else(|| {
+            unsuitable(format!(
+                "holds {} lovelace, but {total_collateral} are needed as collateral",
+                lovelace_of(&output.value)
+            ))
+        })?;
+
+    Ok(PostAlonzoTransactionOutput {
+        address: output.address.clone(),
+        value,
+        datum_option: None,
+        script_ref: None,
+    })
+}
+
+// Forfeit the collateral inputs entirely on script failure, instead of getting the excess back. The
+// ledger then requires pure-ada collateral that, on its own, covers the total collateral.
+fn without_collateral_return(mut tx: Tx, resolved_inputs: &[ResolvedInput]) -> Result<Tx, Error> {
+    let Some(total_collateral) = tx.transaction_body.total_collateral else {
+        return Ok(tx);
+    };
+
+    validate_pure_ada_collateral(&tx, resolved_inputs)?;
+
+    let lovelace = tx
+        .transaction_body
+        .collateral
+        .iter()
+        .flat_map(|xs| xs.iter())
+        .filter_map(|input| resolved_inputs.iter().find(|r| &r.input == input))
+        .map(|r| lovelace_of(&expect_post_alonzo(&r.output).value))
+        .sum::<u64>();
+
+    if lovelace < total_collateral {
+        return Err(Error::InsufficientCollateral {
+            lovelace,
+            required: total_collateral,
+        });
+    }
+
+    tx.transaction_body.collateral_return = None;
+    tx.transaction_body.total_collateral = None;
+    Ok(tx)
+}
+
+// Ensure that every redeemer points to an existing input, policy, certificate, etc. Indices are
+// positional, so any change in the ordering of the transaction's elements may otherwise silently
+// desynchronize them; which only manifests as an opaque script evaluation failure.
+pub fn validate_redeemers(tx: &Tx) -> Result<(), Error> {
+    let body = &tx.transaction_body;
+
+    let redeemers = match tx.transaction_witness_set.redeemer {
+        Some(ref redeemers) => redeemers.iter().map(|(key, _)| key).collect::<Vec<_>>(),
+        None => return Ok(()),
+    };
+
+    for RedeemersKey { tag, index } in redeemers {
+        let len = match tag {
+            RedeemerTag::Spend => body.inputs.len(),
+            RedeemerTag::Mint => body.mint.as_ref().map(|m| m.len()).unwrap_or(0),
+            RedeemerTag::Cert => body.certificates.as_ref().map(|c| c.len()).unwrap_or(0),
+            RedeemerTag::Reward => body.withdrawals.as_ref().map(|w| w.len()).unwrap_or(0),
+            RedeemerTag::Vote => body
+                .voting_procedures
+                .as_ref()
+                .map(|v| v.len())
+                .unwrap_or(0),
+            RedeemerTag::Propose => body
+                .proposal_procedures
+                .as_ref()
+                .map(|p| p.len())
+                .unwrap_or(0),
+        };
+
+        if *index as usize >= len {
+            return Err(Error::RedeemerIndexOutOfRange {
+                tag: *tag,
+                index: *index,
+            });
+        }
+    }
+
+    Ok(())
+}
+
 pub fn expect_post_alonzo(output: &TransactionOutput) -> &PostAlonzoTransactionOutput {
     if let TransactionOutput::PostAlonzo(ref o) = output {
         o
@@ -391,3 +1499,18 @@ pub fn expect_post_alonzo(output: &TransactionOutput) -> &PostAlonzoTransactionO
         panic!("expected PostAlonzo output but got a legacy one.")
     }
 }
+
+// The key that must witness spending the given output, when locked by one (rather than a script).
+pub fn payment_key_hash(output: &TransactionOutput) -> Option<Hash<28>> {
+    let address = match output {
+        TransactionOutput::PostAlonzo(output) => &output.address,
+        TransactionOutput::Legacy(output) => &output.address,
+    };
+    match Address::from_bytes(address).ok()? {
+        Address::Shelley(address) => match address.payment() {
+            ShelleyPaymentPart::Key(key_hash) => Some(*key_hash),
+            ShelleyPaymentPart::Script(_) => None,
+        },
+        _ => None,
+    }
+}
@@ -0,0 +1,59 @@
+//  This Source Code Form is subject to the terms of the Mozilla Public
+//  License, v. 2.0. If a copy of the MPL was not distributed with this
+//  file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::{
+    cell::RefCell,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+/// A cheap, shareable, handle collecting how long each phase of a command took. A disabled handle
+/// records nothing.
+#[derive(Debug, Default, Clone)]
+pub struct Timings(Option<Rc<Phases>>);
+
+#[derive(Debug)]
+pub struct Phases {
+    started: Instant,
+    phases: RefCell<Vec<(String, Duration)>>,
+}
+
+impl Timings {
+    pub fn new(enabled: bool) -> Self {
+        Timings(enabled.then(|| {
+            Rc::new(Phases {
+                started: Instant::now(),
+                phases: RefCell::new(vec![]),
+            })
+        }))
+    }
+
+    pub fn record(&self, phase: impl Into<String>, since: Instant) {
+        if let Some(ref inner) = self.0 {
+            inner
+                .phases
+                .borrow_mut()
+                .push((phase.into(), since.elapsed()));
+        }
+    }
+
+    pub fn summary(&self) {
+        if let Some(ref inner) = self.0 {
+            let phases = inner.phases.borrow();
+            let width = phases
+                .iter()
+                .map(|(phase, _)| phase.len())
+                .max()
+                .unwrap_or(0)
+                .max("total".len());
+            eprintln!("timings:");
+            for (phase, elapsed) in phases.iter() {
+                eprintln!("  {phase:<width$}  {elapsed:>10.2?}");
+            }
+            // NOTE: phases may nest (e.g. the minting lookup fetches transactions), so the total
+            // is measured as wall-clock time rather than summed.
+            eprintln!("  {:<width$}  {:>10.2?}", "total", inner.started.elapsed());
+        }
+    }
+}
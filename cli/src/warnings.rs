@@ -0,0 +1,107 @@
+//  This Source Code Form is subject to the terms of the Mozilla Public
+//  License, v. 2.0. If a copy of the MPL was not distributed with this
+//  file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::{cell::RefCell, fmt, rc::Rc};
+
+/// Advisory conditions met while building a transaction. None of them prevents the build, but
+/// each is worth a second look before signing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Warning {
+    AlreadyVoted {
+        drep_id: String,
+        choice: String,
+        proposal: String,
+    },
+    AnchorNotJsonLd {
+        url: String,
+    },
+    AnchorUnexpectedContentType {
+        url: String,
+        content_type: String,
+    },
+    BestEffort {
+        attempts: u8,
+        fee: u64,
+    },
+    NoSigners,
+    SuspiciousCollateralPercent {
+        percent: u64,
+    },
+    UnconfirmedProposal {
+        proposal: String,
+    },
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Warning::AlreadyVoted {
+                drep_id,
+                choice,
+                proposal,
+            } => {
+                write!(f, "{drep_id} has already voted {choice} on {proposal}.")
+            }
+            Warning::AnchorNotJsonLd { url } => {
+                write!(f, "anchor at {url} is JSON, but not JSON-LD (missing '@context').")
+            }
+            Warning::AnchorUnexpectedContentType { url, content_type } => write!(
+                f,
+                "anchor at {url} served with content-type '{content_type}' instead of JSON."
+            ),
+            Warning::BestEffort { attempts, fee } => write!(
+                f,
+                "did not converge after {attempts} attempts; emitting a best-effort transaction which may be over-paying fees ({fee} lovelace)."
+            ),
+            Warning::NoSigners => write!(
+                f,
+                "no --administrator given; the transaction requires no signature and can be submitted by anyone."
+            ),
+            Warning::SuspiciousCollateralPercent { percent } => write!(
+                f,
+                "the provider reports a collateral percentage of {percent}, which looks off (expected a percentage such as 150); consider --collateral-percent."
+            ),
+            Warning::UnconfirmedProposal { proposal } => write!(
+                f,
+                "proposal {proposal} isn't on-chain (yet?); the vote will be rejected by the ledger if it never shows up."
+            ),
+        }
+    }
+}
+
+/// A cheap, shareable, handle collecting warnings as a command goes; so they can all be rendered
+/// at once, after the transaction.
+#[derive(Debug, Default, Clone)]
+pub struct Warnings(Rc<RefCell<Vec<Warning>>>);
+
+impl Warnings {
+    pub fn push(&self, warning: Warning) {
+        let mut warnings = self.0.borrow_mut();
+        if !warnings.contains(&warning) {
+            warnings.push(warning);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.borrow().is_empty()
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        self.0
+            .borrow()
+            .iter()
+            .map(|warning| warning.to_string())
+            .collect()
+    }
+
+    pub fn render(&self) {
+        for warning in self.0.borrow().iter() {
+            eprintln!("warning: {warning}");
+        }
+    }
+}